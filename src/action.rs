@@ -0,0 +1,316 @@
+use crate::configuration::{ConfigurationAction, SourceVersion, TypeOperation};
+
+use serde_json::{Map, Value, json};
+
+const VALEUR_ABSENTE: &str = "inconnu";
+
+enum Segment {
+    Texte(String),
+    Variable(String),
+}
+
+/// Nom d'index évalué pour chaque document : `decisions-{juridiction}-{annee_lecture}`.
+///
+/// Une variable désigne un champ du document. `annee_X` et `mois_X` extraient
+/// l'année et le mois du champ date `date_X`. Une variable sans valeur est
+/// remplacée par `inconnu`.
+pub struct ModeleIndex {
+    segments: Vec<Segment>,
+}
+
+impl ModeleIndex {
+    pub fn analyser(modele: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut reste = modele;
+        while let Some(debut) = reste.find('{') {
+            if debut > 0 {
+                segments.push(Segment::Texte(reste[..debut].to_string()));
+            }
+            let fin = reste[debut..].find('}').ok_or_else(|| {
+                format!("accolade non fermée dans le modèle d'index « {} »", modele)
+            })?;
+            let variable = reste[debut + 1..debut + fin].trim();
+            if variable.is_empty() || variable.contains('{') {
+                return Err(format!(
+                    "variable invalide dans le modèle d'index « {} »",
+                    modele
+                ));
+            }
+            segments.push(Segment::Variable(variable.to_string()));
+            reste = &reste[debut + fin + 1..];
+        }
+        if reste.contains('}') {
+            return Err(format!(
+                "accolade non ouverte dans le modèle d'index « {} »",
+                modele
+            ));
+        }
+        if !reste.is_empty() {
+            segments.push(Segment::Texte(reste.to_string()));
+        }
+        if segments.is_empty() {
+            return Err("le modèle d'index est vide".to_string());
+        }
+        Ok(ModeleIndex { segments })
+    }
+
+    pub fn evaluer(&self, document: &Value) -> String {
+        let mut nom = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Texte(texte) => nom.push_str(texte),
+                Segment::Variable(variable) => nom.push_str(
+                    &valeur_variable(document, variable)
+                        .unwrap_or_else(|| VALEUR_ABSENTE.to_string()),
+                ),
+            }
+        }
+        nettoyer_nom_index(&nom)
+    }
+}
+
+fn valeur_variable(document: &Value, variable: &str) -> Option<String> {
+    if let Some(valeur) = valeur_texte(&document[variable]) {
+        return Some(valeur);
+    }
+    let (champ, plage) = if let Some(suffixe) = variable.strip_prefix("annee_") {
+        (suffixe, 0..4)
+    } else if let Some(suffixe) = variable.strip_prefix("mois_") {
+        (suffixe, 5..7)
+    } else {
+        return None;
+    };
+    document[format!("date_{}", champ)]
+        .as_str()
+        .and_then(|date| date.get(plage))
+        .map(str::to_string)
+}
+
+/// Elasticsearch n'accepte que des noms d'index en minuscules, sans espace
+/// ni caractère réservé, ne commençant pas par `-`, `_` ou `+`, et différents
+/// de `.` et `..`.
+fn nettoyer_nom_index(nom: &str) -> String {
+    let nom: String = nom
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            '\\' | '/' | '*' | '?' | '"' | '<' | '>' | '|' | ' ' | ',' | '#' | ':' => '_',
+            _ => c,
+        })
+        .collect();
+    let nom = nom.trim_start_matches(['-', '_', '+']);
+    match nom {
+        "" | "." | ".." => VALEUR_ABSENTE.to_string(),
+        _ => nom.to_string(),
+    }
+}
+
+/// Construit la ligne d'action bulk qui précède chaque document.
+pub struct ConstructeurAction {
+    configuration: ConfigurationAction,
+    modele_index: ModeleIndex,
+}
+
+impl ConstructeurAction {
+    pub fn new(configuration: &ConfigurationAction) -> Result<Self, String> {
+        Ok(ConstructeurAction {
+            configuration: configuration.clone(),
+            modele_index: ModeleIndex::analyser(&configuration.index)?,
+        })
+    }
+
+    /// Index et clé de routage du document.
+    pub fn destination(&self, document: &Value) -> (String, Option<String>) {
+        let routing = self
+            .configuration
+            .routing
+            .as_ref()
+            .and_then(|champ| valeur_texte(&document[champ.as_str()]));
+        (self.modele_index.evaluer(document), routing)
+    }
+
+    pub fn construire(&self, document: &Value) -> String {
+        let id = document["id"].as_str().unwrap_or_default();
+        let (index, routing) = self.destination(document);
+        let mut meta = Map::new();
+        meta.insert("_index".to_string(), json!(index));
+        meta.insert("_id".to_string(), json!(id));
+        if let Some(routing) = routing {
+            meta.insert("routing".to_string(), json!(routing));
+        }
+        if let Some(pipeline) = &self.configuration.pipeline {
+            meta.insert("pipeline".to_string(), json!(pipeline));
+        }
+        if let Some(version) = self
+            .configuration
+            .version
+            .and_then(|source| version_externe(source, document))
+        {
+            meta.insert("version".to_string(), json!(version));
+            meta.insert("version_type".to_string(), json!("external_gte"));
+        }
+        let operation = match self.configuration.type_operation {
+            TypeOperation::Index => "index",
+            TypeOperation::Create => "create",
+        };
+        json!({ operation: meta }).to_string()
+    }
+
+    pub fn construire_suppression(&self, index: &str, id: &str, routing: Option<&str>) -> String {
+        let mut meta = Map::new();
+        meta.insert("_index".to_string(), json!(index));
+        meta.insert("_id".to_string(), json!(id));
+        if let Some(routing) = routing {
+            meta.insert("routing".to_string(), json!(routing));
+        }
+        json!({ "delete": meta }).to_string()
+    }
+}
+
+/// Avec `external_gte`, Elasticsearch refuse (409) toute version inférieure à
+/// celle déjà indexée : rejouer un export plus ancien ne remplace donc jamais
+/// une décision plus récente, et rejouer le même export est sans effet.
+fn version_externe(source: SourceVersion, document: &Value) -> Option<u64> {
+    match source {
+        SourceVersion::Date => document["date_mise_jour"]
+            .as_str()
+            .map(|date| date.replace('-', ""))
+            .and_then(|date| date.parse().ok()),
+    }
+}
+
+fn valeur_texte(valeur: &Value) -> Option<String> {
+    match valeur {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        // Champ répété : la première occurrence.
+        Value::Array(valeurs) => valeurs.first().and_then(valeur_texte),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration() -> ConfigurationAction {
+        ConfigurationAction {
+            index: "{juridiction}_decisions".to_string(),
+            routing: Some("juridiction".to_string()),
+            pipeline: None,
+            type_operation: TypeOperation::Index,
+            version: None,
+        }
+    }
+
+    fn action(configuration: ConfigurationAction, document: &Value) -> Value {
+        let constructeur = ConstructeurAction::new(&configuration).unwrap();
+        serde_json::from_str(&constructeur.construire(document)).unwrap()
+    }
+
+    #[test]
+    fn evalue_le_modele_d_index() {
+        let document = json!({
+            "id": "DTA_1",
+            "juridiction": "TA",
+            "date_lecture": "2023-04-05",
+            "formation_chambre": "3",
+            "avocats": ["Me A", "Me B"],
+            "vide": ""
+        });
+        for (modele, attendu) in [
+            ("decisions", "decisions"),
+            ("{juridiction}_decisions", "ta_decisions"),
+            (
+                "decisions-{juridiction}-{annee_lecture}",
+                "decisions-ta-2023",
+            ),
+            (
+                "decisions-{ annee_lecture }{mois_lecture}",
+                "decisions-202304",
+            ),
+            ("decisions-{formation_chambre}", "decisions-3"),
+            ("decisions-{annee_audience}", "decisions-inconnu"),
+            ("decisions-{absent}-{vide}", "decisions-inconnu-inconnu"),
+            ("{avocats}", "me_a"),
+            ("Décisions/{juridiction}:*?", "décisions_ta___"),
+            ("_{juridiction}", "ta"),
+            ("-+{juridiction}-decisions", "ta-decisions"),
+            ("/{vide}", "inconnu"),
+            ("..", "inconnu"),
+            ("_{vide}", "inconnu"),
+        ] {
+            let modele_index = ModeleIndex::analyser(modele).unwrap();
+            assert_eq!(modele_index.evaluer(&document), attendu, "{}", modele);
+        }
+    }
+
+    #[test]
+    fn refuse_les_modeles_d_index_invalides() {
+        for modele in [
+            "",
+            "decisions-{juridiction",
+            "decisions-}",
+            "{}",
+            "{ }",
+            "{a{b}",
+        ] {
+            assert!(ModeleIndex::analyser(modele).is_err(), "{}", modele);
+        }
+    }
+
+    #[test]
+    fn construit_l_action_avec_index_routage_et_pipeline() {
+        let document = json!({"id": "DTA_1", "juridiction": "TA"});
+        assert_eq!(
+            action(configuration(), &document),
+            json!({"index": {"_index": "ta_decisions", "_id": "DTA_1", "routing": "TA"}})
+        );
+        assert_eq!(
+            action(
+                ConfigurationAction {
+                    routing: None,
+                    pipeline: Some("nettoyage".to_string()),
+                    type_operation: TypeOperation::Create,
+                    ..configuration()
+                },
+                &document
+            ),
+            json!({"create": {"_index": "ta_decisions", "_id": "DTA_1", "pipeline": "nettoyage"}})
+        );
+        // Sans valeur, pas de clé de routage.
+        assert_eq!(
+            action(configuration(), &json!({"id": "X_1", "juridiction": null})),
+            json!({"index": {"_index": "inconnu_decisions", "_id": "X_1"}})
+        );
+    }
+
+    #[test]
+    fn versionne_avec_la_date_de_mise_a_jour() {
+        let versionnee = ConfigurationAction {
+            version: Some(SourceVersion::Date),
+            ..configuration()
+        };
+        let meta = action(
+            versionnee.clone(),
+            &json!({"id": "DTA_1", "juridiction": "TA", "date_mise_jour": "2023-02-01"}),
+        );
+        assert_eq!(meta["index"]["version"], 20230201);
+        assert_eq!(meta["index"]["version_type"], "external_gte");
+        let meta = action(versionnee, &json!({"id": "DTA_1", "juridiction": "TA"}));
+        assert!(meta["index"].get("version").is_none());
+    }
+
+    #[test]
+    fn construit_la_suppression() {
+        let constructeur = ConstructeurAction::new(&configuration()).unwrap();
+        assert_eq!(
+            constructeur.construire_suppression("ta_decisions", "DTA_1", Some("TA")),
+            r#"{"delete":{"_index":"ta_decisions","_id":"DTA_1","routing":"TA"}}"#
+        );
+        assert_eq!(
+            constructeur.construire_suppression("ta_decisions", "DTA_1", None),
+            r#"{"delete":{"_index":"ta_decisions","_id":"DTA_1"}}"#
+        );
+    }
+}
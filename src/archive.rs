@@ -0,0 +1,265 @@
+use crossbeam_channel::{Receiver, Sender, bounded};
+use flate2::read::GzDecoder;
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Entrées lues d'avance par archive, en attente d'analyse.
+const ENTREES_EN_ATTENTE: usize = 256;
+
+/// Fichier `.xml` extrait d'une archive, lu en mémoire.
+// `compter_balises_xml` n'utilise que le contenu.
+#[allow(dead_code)]
+pub struct EntreeArchive {
+    /// Chemin de l'entrée dans l'archive.
+    pub chemin: String,
+    pub mtime_ns: u64,
+    pub contenu: Vec<u8>,
+}
+
+enum FormatArchive {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+fn format_archive(chemin: &Path) -> Option<FormatArchive> {
+    let nom = chemin.file_name()?.to_string_lossy().to_lowercase();
+    if nom.ends_with(".tar.gz") || nom.ends_with(".tgz") {
+        Some(FormatArchive::TarGz)
+    } else if nom.ends_with(".tar") {
+        Some(FormatArchive::Tar)
+    } else if nom.ends_with(".zip") {
+        Some(FormatArchive::Zip)
+    } else {
+        None
+    }
+}
+
+pub fn est_archive(chemin: &Path) -> bool {
+    format_archive(chemin).is_some()
+}
+
+fn est_xml(chemin: &str) -> bool {
+    chemin.to_lowercase().ends_with(".xml")
+}
+
+/// Lit les entrées `.xml` de l'archive dans un thread dédié. Les archives
+/// `.tar.gz` ne se lisent que séquentiellement : l'analyse des entrées reçues
+/// peut en revanche être parallélisée (`par_bridge`).
+///
+/// Une archive tronquée ou corrompue produit une dernière erreur après les
+/// entrées lues jusque-là.
+pub fn entrees_xml(archive: PathBuf) -> Receiver<io::Result<EntreeArchive>> {
+    let (emetteur, recepteur) = bounded(ENTREES_EN_ATTENTE);
+    std::thread::spawn(move || {
+        let resultat = match format_archive(&archive) {
+            Some(FormatArchive::TarGz) => File::open(&archive)
+                .and_then(|f| lire_tar(GzDecoder::new(BufReader::new(f)), &emetteur)),
+            Some(FormatArchive::Tar) => {
+                File::open(&archive).and_then(|f| lire_tar(BufReader::new(f), &emetteur))
+            }
+            Some(FormatArchive::Zip) => File::open(&archive).and_then(|f| lire_zip(f, &emetteur)),
+            None => Ok(()),
+        };
+        if let Err(e) = resultat {
+            let _ = emetteur.send(Err(e));
+        }
+    });
+    recepteur
+}
+
+fn lire_tar<R: Read>(lecteur: R, emetteur: &Sender<io::Result<EntreeArchive>>) -> io::Result<()> {
+    let mut archive = tar::Archive::new(lecteur);
+    for entree in archive.entries()? {
+        let mut entree = entree?;
+        if !entree.header().entry_type().is_file() {
+            continue;
+        }
+        let chemin = entree.path()?.to_string_lossy().to_string();
+        if !est_xml(&chemin) {
+            continue;
+        }
+        let mtime_ns = entree.header().mtime().unwrap_or(0) * 1_000_000_000;
+        let mut contenu = Vec::with_capacity(entree.size() as usize);
+        entree.read_to_end(&mut contenu)?;
+        if contenu.len() as u64 != entree.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("entrée {} tronquée", chemin),
+            ));
+        }
+        let entree = EntreeArchive {
+            chemin,
+            mtime_ns,
+            contenu,
+        };
+        if emetteur.send(Ok(entree)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn lire_zip(fichier: File, emetteur: &Sender<io::Result<EntreeArchive>>) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(fichier))?;
+    for i in 0..archive.len() {
+        let mut entree = archive.by_index(i)?;
+        let chemin = entree.name()?.to_string();
+        if !entree.is_file() || !est_xml(&chemin) {
+            continue;
+        }
+        let mtime_ns = entree
+            .last_modified()
+            .and_then(|date| {
+                chrono::NaiveDate::from_ymd_opt(
+                    date.year() as i32,
+                    date.month() as u32,
+                    date.day() as u32,
+                )?
+                .and_hms_opt(
+                    date.hour() as u32,
+                    date.minute() as u32,
+                    date.second() as u32,
+                )
+            })
+            .map(|date| date.and_utc().timestamp().max(0) as u64 * 1_000_000_000)
+            .unwrap_or(0);
+        let mut contenu = Vec::with_capacity(entree.size() as usize);
+        entree.read_to_end(&mut contenu)?;
+        let entree = EntreeArchive {
+            chemin,
+            mtime_ns,
+            contenu,
+        };
+        if emetteur.send(Ok(entree)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use std::io::{Cursor, Write};
+
+    const XML: &[u8] = b"<Document><Identification>DTA_1</Identification></Document>";
+
+    fn tar(entrees: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut constructeur = tar::Builder::new(Vec::new());
+        for (chemin, contenu) in entrees {
+            let mut entete = tar::Header::new_gnu();
+            entete.set_size(contenu.len() as u64);
+            entete.set_mode(0o644);
+            entete.set_mtime(1_704_448_800);
+            entete.set_cksum();
+            constructeur
+                .append_data(&mut entete, chemin, *contenu)
+                .unwrap();
+        }
+        constructeur.into_inner().unwrap()
+    }
+
+    fn tar_gz(entrees: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut compresseur = GzEncoder::new(Vec::new(), Compression::default());
+        compresseur.write_all(&tar(entrees)).unwrap();
+        compresseur.finish().unwrap()
+    }
+
+    fn zip(entrees: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut ecrivain = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(2024, 1, 5, 10, 0, 0).unwrap());
+        ecrivain.add_directory("JADE/", options).unwrap();
+        for (chemin, contenu) in entrees {
+            ecrivain.start_file(*chemin, options).unwrap();
+            ecrivain.write_all(contenu).unwrap();
+        }
+        ecrivain.finish().unwrap().into_inner()
+    }
+
+    /// Écrit l'archive dans un fichier temporaire et en lit toutes les entrées.
+    fn lire(nom: &str, octets: &[u8]) -> Vec<io::Result<EntreeArchive>> {
+        let chemin =
+            std::env::temp_dir().join(format!("xml_to_elastic_{}_{}", std::process::id(), nom));
+        std::fs::write(&chemin, octets).unwrap();
+        let entrees = entrees_xml(chemin.clone()).into_iter().collect();
+        std::fs::remove_file(&chemin).unwrap();
+        entrees
+    }
+
+    fn chemins(entrees: &[io::Result<EntreeArchive>]) -> Vec<&str> {
+        entrees
+            .iter()
+            .map(|entree| entree.as_ref().unwrap().chemin.as_str())
+            .collect()
+    }
+
+    const ENTREES: &[(&str, &[u8])] = &[
+        ("JADE/DTA_1.xml", XML),
+        ("JADE/lisezmoi.txt", b"pas du XML"),
+        ("JADE/DTA_2.XML", XML),
+    ];
+
+    #[test]
+    fn lit_les_entrees_xml_d_un_tar() {
+        let entrees = lire("a.tar", &tar(ENTREES));
+        assert_eq!(chemins(&entrees), ["JADE/DTA_1.xml", "JADE/DTA_2.XML"]);
+        let entree = entrees[0].as_ref().unwrap();
+        assert_eq!(entree.contenu, XML);
+        assert_eq!(entree.mtime_ns, 1_704_448_800 * 1_000_000_000);
+    }
+
+    #[test]
+    fn lit_les_entrees_xml_d_un_tar_gz() {
+        let octets = tar_gz(ENTREES);
+        assert_eq!(
+            chemins(&lire("a.tar.gz", &octets)),
+            ["JADE/DTA_1.xml", "JADE/DTA_2.XML"]
+        );
+        assert_eq!(chemins(&lire("a.tgz", &octets)).len(), 2);
+    }
+
+    #[test]
+    fn lit_les_entrees_xml_d_un_zip() {
+        let entrees = lire("a.zip", &zip(ENTREES));
+        assert_eq!(chemins(&entrees), ["JADE/DTA_1.xml", "JADE/DTA_2.XML"]);
+        let entree = entrees[1].as_ref().unwrap();
+        assert_eq!(entree.contenu, XML);
+        assert_eq!(entree.mtime_ns, 1_704_448_800 * 1_000_000_000);
+    }
+
+    #[test]
+    fn signale_une_archive_tronquee_apres_les_entrees_lues() {
+        let contenu = vec![b'x'; 4096];
+        let octets = tar(&[("DTA_1.xml", XML), ("DTA_2.xml", &contenu)]);
+        // Coupée au milieu de la seconde entrée.
+        let entrees = lire("tronquee.tar", &octets[..2048]);
+        assert_eq!(entrees.len(), 2);
+        assert_eq!(entrees[0].as_ref().unwrap().chemin, "DTA_1.xml");
+        assert!(entrees[1].is_err());
+
+        let octets = tar_gz(&[("DTA_1.xml", XML), ("DTA_2.xml", &contenu)]);
+        let entrees = lire("tronquee.tar.gz", &octets[..octets.len() / 2]);
+        assert!(entrees.last().unwrap().is_err());
+
+        let entrees = lire("corrompue.zip", b"PK pas une archive");
+        assert_eq!(entrees.len(), 1);
+        assert!(entrees[0].is_err());
+    }
+
+    #[test]
+    fn reconnait_les_archives_a_leur_extension() {
+        assert!(est_archive(Path::new("JADE_20240101.TAR.GZ")));
+        assert!(est_archive(Path::new("a.tgz")));
+        assert!(est_archive(Path::new("a.zip")));
+        assert!(!est_archive(Path::new("a.xml")));
+        assert!(!est_archive(Path::new("a.gz")));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::solutions::{issue_solution, normaliser_solution, regles, resultat_solution};
+use crate::texte::{
+    NonReconnue, formation_jugement, normaliser_date, normaliser_espaces, normaliser_type_recours,
+    supprimer_balises_html, type_formation_jugement,
+};
+
+use html_escape::decode_html_entities;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Correspondance par défaut, intégrée au binaire.
+const CHAMPS_DEFAUT: &str = include_str!("../champs.toml");
+
+/// Champs de `Decision` calculés par l'outil, qui ne peuvent pas provenir
+/// d'une balise.
+const CHAMPS_RESERVES: [&str; 6] = [
+    "juridiction",
+    "extra",
+    "archive",
+    "entree_archive",
+    "qualite_parsing",
+    "chemin_source",
+];
+
+/// Type Elasticsearch du champ dans le modèle d'index.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeChamp {
+    #[default]
+    Keyword,
+    /// Texte analysé en français.
+    Texte,
+    /// Date `yyyy-MM-dd`.
+    Date,
+    /// `true`/`false`, seulement avec la normalisation `serie_identique`.
+    Booleen,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalisation {
+    #[default]
+    Aucune,
+    /// `normaliser_date`, la valeur est écartée si la date est invalide.
+    Date,
+    /// Balises HTML retirées et espaces normalisés.
+    Html,
+    /// Libellé de la première règle de `solutions.toml` reconnaissant la
+    /// valeur.
+    Solution,
+    /// `issue` de cette règle.
+    Issue,
+    /// `resultat` de cette règle.
+    Resultat,
+    /// La valeur contient le motif `serie_identique` de `solutions.toml`.
+    #[serde(rename = "serie_identique")]
+    SerieIdentique,
+    /// `normaliser_type_recours`.
+    #[serde(rename = "type_recours")]
+    TypeRecours,
+    /// Type de la formation de jugement : chambre, juge unique, référé,
+    /// plénière ou section.
+    #[serde(rename = "formation_type")]
+    FormationType,
+    /// Numéro de la chambre de la formation de jugement.
+    #[serde(rename = "formation_chambre")]
+    FormationChambre,
+    /// Numéro de la section ou de la sous-section.
+    #[serde(rename = "formation_sous_section")]
+    FormationSousSection,
+}
+
+impl Normalisation {
+    /// Valeur du champ, `None` si la valeur brute est écartée. Les entités
+    /// (`&amp;`, `&#233;`, `&eacute;`) sont décodées ici une seule fois, après
+    /// la suppression des balises pour le HTML. Les types de recours et
+    /// formations non reconnus sont signalés dans `non_reconnues`.
+    pub fn appliquer(self, brute: &str, non_reconnues: &mut Vec<NonReconnue>) -> Option<Value> {
+        let html = brute;
+        let decodee = decode_html_entities(brute);
+        let brute = decodee.as_ref();
+        let texte = match self {
+            Normalisation::Aucune => Some(brute.to_string()),
+            Normalisation::Date => normaliser_date(brute),
+            Normalisation::Html => {
+                Some(normaliser_espaces(&supprimer_balises_html(html))).filter(|t| !t.is_empty())
+            }
+            Normalisation::Solution => Some(normaliser_solution(brute)),
+            Normalisation::Issue => issue_solution(brute),
+            Normalisation::Resultat => resultat_solution(brute),
+            Normalisation::SerieIdentique => {
+                return Some(Value::Bool(regles().est_serie_identique(brute)));
+            }
+            Normalisation::TypeRecours => normaliser_type_recours(brute, non_reconnues),
+            Normalisation::FormationType => type_formation_jugement(brute, non_reconnues),
+            Normalisation::FormationChambre => formation_jugement(brute.trim())
+                .chambre
+                .map(|chambre| chambre.to_string()),
+            Normalisation::FormationSousSection => formation_jugement(brute.trim())
+                .sous_section
+                .map(|sous_section| sous_section.to_string()),
+        };
+        texte.map(Value::String)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefinitionChamp {
+    /// Chemin de la balise, par exemple `Document/Dossier/Solution`.
+    pub chemin: String,
+    pub nom: String,
+    #[serde(rename = "type", default)]
+    pub type_champ: TypeChamp,
+    #[serde(default)]
+    pub normalisation: Normalisation,
+    /// Balise pouvant se répéter : le champ devient une liste de toutes ses
+    /// occurrences au lieu de la dernière.
+    #[serde(default)]
+    pub multiple: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FichierChamps {
+    #[serde(rename = "champ")]
+    champs: Vec<DefinitionChamp>,
+}
+
+/// Champs extraits des fichiers XML, dans l'ordre du fichier de
+/// correspondance.
+pub struct TableChamps {
+    pub definitions: Vec<DefinitionChamp>,
+    chemins: HashSet<String>,
+    noms: Vec<String>,
+    /// Conserve les autres feuilles de `Document` dans `Decision::extra`.
+    pub extra: bool,
+}
+
+impl TableChamps {
+    pub fn depuis_toml(contenu: &str) -> Result<Self, String> {
+        let mut fichier: FichierChamps = toml::from_str(contenu).map_err(|e| e.to_string())?;
+        let mut chemins = HashSet::new();
+        let mut noms = Vec::new();
+        let mut vus = HashSet::new();
+        for definition in fichier.champs.iter_mut() {
+            definition.chemin = definition.chemin.trim_matches('/').to_string();
+            if definition.nom.is_empty() || definition.chemin.is_empty() {
+                return Err("chaque champ doit avoir un chemin et un nom".to_string());
+            }
+            if CHAMPS_RESERVES.contains(&definition.nom.as_str()) {
+                return Err(format!(
+                    "le champ {} est calculé par l'outil et ne peut pas être extrait",
+                    definition.nom
+                ));
+            }
+            if (definition.type_champ == TypeChamp::Booleen)
+                != (definition.normalisation == Normalisation::SerieIdentique)
+            {
+                return Err(format!(
+                    "le champ {}: le type booleen va avec la normalisation serie_identique",
+                    definition.nom
+                ));
+            }
+            if definition.nom == "id" && definition.multiple {
+                return Err("le champ id ne peut pas être multiple".to_string());
+            }
+            if !vus.insert(definition.nom.clone()) {
+                return Err(format!("le champ {} est déclaré deux fois", definition.nom));
+            }
+            chemins.insert(definition.chemin.clone());
+            noms.push(definition.nom.clone());
+        }
+        if !vus.contains("id") {
+            return Err("le champ id est obligatoire".to_string());
+        }
+        Ok(TableChamps {
+            definitions: fichier.champs,
+            chemins,
+            noms,
+            extra: false,
+        })
+    }
+
+    pub fn charger(chemin: &Path) -> Result<Self, String> {
+        let contenu = std::fs::read_to_string(chemin)
+            .map_err(|e| format!("lecture de {} impossible: {}", chemin.display(), e))?;
+        Self::depuis_toml(&contenu).map_err(|e| format!("{}: {}", chemin.display(), e))
+    }
+
+    pub fn par_defaut() -> Self {
+        Self::depuis_toml(CHAMPS_DEFAUT).expect("champs.toml intégré invalide")
+    }
+
+    pub fn est_chemin_autorise(&self, chemin: &str) -> bool {
+        self.chemins.contains(chemin)
+    }
+
+    /// Noms des champs produits, dans l'ordre du fichier.
+    pub fn noms(&self) -> &[String] {
+        &self.noms
+    }
+
+    /// Type du champ `nom`.
+    pub fn type_champ(&self, nom: &str) -> Option<TypeChamp> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.nom == nom)
+            .map(|definition| definition.type_champ)
+    }
+}
+
+static CHAMPS: OnceLock<TableChamps> = OnceLock::new();
+
+/// Remplace la correspondance par défaut ; à appeler avant la première
+/// analyse, sinon la table déjà utilisée est conservée et l'appel échoue.
+pub fn installer_champs(table: TableChamps) -> Result<(), String> {
+    CHAMPS
+        .set(table)
+        .map_err(|_| "table des champs déjà utilisée, non remplacée".to_string())
+}
+
+pub fn champs() -> &'static TableChamps {
+    CHAMPS.get_or_init(TableChamps::par_defaut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str =
+        "[[champ]]\nchemin = \"Document/Donnees_Techniques/Identification\"\nnom = \"id\"\n";
+
+    fn table(champs: &str) -> Result<TableChamps, String> {
+        TableChamps::depuis_toml(&format!("{}{}", ID, champs))
+    }
+
+    #[test]
+    fn accepte_la_table_integree() {
+        let table = TableChamps::par_defaut();
+        assert_eq!(table.noms()[0], "id");
+        assert!(table.est_chemin_autorise("Document/Dossier/Solution"));
+        assert!(table.type_champ("serie_identique") == Some(TypeChamp::Booleen));
+    }
+
+    #[test]
+    fn refuse_les_noms_reserves() {
+        for nom in CHAMPS_RESERVES {
+            let erreur = table(&format!(
+                "[[champ]]\nchemin = \"Document/Dossier/X\"\nnom = \"{}\"\n",
+                nom
+            ))
+            .err()
+            .unwrap();
+            assert!(erreur.contains("calculé par l'outil"), "{}", erreur);
+        }
+    }
+
+    #[test]
+    fn refuse_les_noms_en_double() {
+        let erreur = table(
+            "[[champ]]\nchemin = \"Document/Dossier/A\"\nnom = \"numero\"\n\
+             [[champ]]\nchemin = \"Document/Dossier/B\"\nnom = \"numero\"\n",
+        )
+        .err()
+        .unwrap();
+        assert!(
+            erreur.contains("numero est déclaré deux fois"),
+            "{}",
+            erreur
+        );
+
+        let erreur = table("[[champ]]\nchemin = \"Document/Dossier/Id\"\nnom = \"id\"\n")
+            .err()
+            .unwrap();
+        assert!(erreur.contains("id est déclaré deux fois"), "{}", erreur);
+    }
+
+    #[test]
+    fn refuse_les_normalisations_inconnues() {
+        let erreur = table(
+            "[[champ]]\nchemin = \"Document/Dossier/A\"\nnom = \"a\"\nnormalisation = \"majuscules\"\n",
+        )
+        .err()
+        .unwrap();
+        assert!(erreur.contains("majuscules"), "{}", erreur);
+    }
+
+    #[test]
+    fn refuse_de_remplacer_une_table_deja_utilisee() {
+        champs();
+        let erreur = installer_champs(TableChamps::par_defaut()).unwrap_err();
+        assert!(erreur.contains("déjà utilisée"), "{}", erreur);
+    }
+
+    #[test]
+    fn exige_le_champ_id() {
+        let erreur = TableChamps::depuis_toml("[[champ]]\nchemin = \"Document/A\"\nnom = \"a\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(erreur, "le champ id est obligatoire");
+    }
+}
@@ -1,13 +1,16 @@
+use crate::archive::{entrees_xml, est_archive};
 use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::encoding::Decoder;
+use quick_xml::events::{BytesStart, Event};
 use rayon::prelude::*;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::env;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
+mod archive;
 const LIMITE_FICHIERS_DEFAUT: usize = 1000000;
 fn normaliser_juridiction(code: &str) -> String {
     code.chars().take_while(|c| c.is_alphabetic()).collect()
@@ -19,13 +22,28 @@ fn construire_chemin(juridiction: &str, pile: &[String]) -> String {
         format!("{}/{}", juridiction, pile.join("/"))
     }
 }
+fn decoder_nom(decoder: Decoder, octets: &[u8]) -> String {
+    decoder
+        .decode(octets)
+        .map(|nom| nom.into_owned())
+        .unwrap_or_else(|_| String::from_utf8_lossy(octets).into_owned())
+}
+/// Chaque attribut est compté comme le chemin `element/@nom`.
+fn compter_attributs(element: &BytesStart, chemin: &str, compteur: &mut HashMap<String, usize>) {
+    for attribut in element.attributes().flatten() {
+        let nom = decoder_nom(element.decoder(), attribut.key.as_ref());
+        *compteur.entry(format!("{}/@{}", chemin, nom)).or_insert(0) += 1;
+    }
+}
 fn extraire_balises(path: &Path) -> HashMap<String, usize> {
+    match File::open(path) {
+        Ok(f) => extraire_balises_depuis(BufReader::new(f)),
+        Err(_) => HashMap::new(),
+    }
+}
+fn extraire_balises_depuis<R: BufRead>(lecteur: R) -> HashMap<String, usize> {
     let mut compteur = HashMap::new();
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return compteur,
-    };
-    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut reader = Reader::from_reader(lecteur);
     reader.config_mut().trim_text(true);
     let mut buf = Vec::new();
     let mut pile: Vec<String> = Vec::new();
@@ -35,7 +53,7 @@ fn extraire_balises(path: &Path) -> HashMap<String, usize> {
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                let nom = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                let nom = decoder_nom(reader.decoder(), e.name().as_ref());
                 if nom == "Texte_Integral" {
                     dans_texte_integral = true;
                 }
@@ -45,11 +63,12 @@ fn extraire_balises(path: &Path) -> HashMap<String, usize> {
                 pile.push(nom.clone());
                 if !dans_texte_integral {
                     let chemin = construire_chemin(&juridiction, &pile);
+                    compter_attributs(&e, &chemin, &mut compteur);
                     *compteur.entry(chemin).or_insert(0) += 1;
                 }
             }
             Ok(Event::End(e)) => {
-                let nom = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                let nom = decoder_nom(reader.decoder(), e.name().as_ref());
                 if nom == "Texte_Integral" {
                     dans_texte_integral = false;
                 }
@@ -58,20 +77,17 @@ fn extraire_balises(path: &Path) -> HashMap<String, usize> {
                 }
                 pile.pop();
             }
-            Ok(Event::Text(e)) => {
-                if lire_juridiction {
-                    let texte: Cow<str> = e.decode().unwrap_or(Cow::Borrowed(""));
-                    juridiction = normaliser_juridiction(&texte);
-                }
+            Ok(Event::Text(e)) if lire_juridiction => {
+                let texte: Cow<str> = e.decode().unwrap_or(Cow::Borrowed(""));
+                juridiction = normaliser_juridiction(&texte);
             }
-            Ok(Event::Empty(e)) => {
-                if !dans_texte_integral {
-                    let nom = String::from_utf8_lossy(e.name().as_ref()).to_string();
-                    let mut pile_temp = pile.clone();
-                    pile_temp.push(nom);
-                    let chemin = construire_chemin(&juridiction, &pile_temp);
-                    *compteur.entry(chemin).or_insert(0) += 1;
-                }
+            Ok(Event::Empty(e)) if !dans_texte_integral => {
+                let nom = decoder_nom(reader.decoder(), e.name().as_ref());
+                let mut pile_temp = pile.clone();
+                pile_temp.push(nom);
+                let chemin = construire_chemin(&juridiction, &pile_temp);
+                compter_attributs(&e, &chemin, &mut compteur);
+                *compteur.entry(chemin).or_insert(0) += 1;
             }
             Ok(Event::Eof) => break,
             Err(_) => break,
@@ -108,22 +124,65 @@ fn main() {
         println!("Usage: compter_balises_xml DOSSIER");
         return;
     }
-    let fichiers: Vec<PathBuf> = dossiers
+    let (archives, fichiers): (Vec<PathBuf>, Vec<PathBuf>) = dossiers
         .iter()
-        .flat_map(|d| WalkDir::new(d))
+        .flat_map(WalkDir::new)
         .filter_map(|e| e.ok())
         .map(|e| e.path().to_path_buf())
-        .filter(|p| p.extension().map(|e| e == "xml").unwrap_or(false))
-        .take(limite)
-        .collect();
-    let compteur = fichiers
+        .filter(|p| p.extension().map(|e| e == "xml").unwrap_or(false) || est_archive(p))
+        .partition(|p| est_archive(p));
+    let fichiers: Vec<PathBuf> = fichiers.into_iter().take(limite).collect();
+    let mut compteur = fichiers
         .par_iter()
         .map(|p| extraire_balises(p))
         .reduce(HashMap::new, fusion);
-    println!("Fichiers traités: {}", fichiers.len());
+    let mut entrees_archives = 0;
+    for archive in archives {
+        let restantes = limite.saturating_sub(fichiers.len() + entrees_archives);
+        let (nombre, compteur_archive) = entrees_xml(archive.clone())
+            .into_iter()
+            .take(restantes)
+            .filter_map(|entree| {
+                entree
+                    .map_err(|e| eprintln!("Archive illisible {}: {}", archive.display(), e))
+                    .ok()
+            })
+            .par_bridge()
+            .map(|e| (1, extraire_balises_depuis(&e.contenu[..])))
+            .reduce(
+                || (0, HashMap::new()),
+                |(na, a), (nb, b)| (na + nb, fusion(a, b)),
+            );
+        entrees_archives += nombre;
+        compteur = fusion(compteur, compteur_archive);
+    }
+    println!("Fichiers traités: {}", fichiers.len() + entrees_archives);
     let mut v: Vec<_> = compteur.into_iter().collect();
-    v.sort_by(|a, b| b.1.cmp(&a.1));
+    v.sort_by_key(|b| std::cmp::Reverse(b.1));
     for (k, c) in v {
         println!("{:<80} {}", k, c);
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn compte_les_attributs_comme_des_chemins() {
+        let xml = "<Document><Dossier type=\"recours\" numero=\"1\"><Code_Juridiction>TA75</Code_Juridiction><Avocat qualite=\"requerant\"/><Avocat/></Dossier><Decision><Texte_Integral><p class=\"x\">Texte</p></Texte_Integral></Decision></Document>";
+        let compteur = extraire_balises_depuis(xml.as_bytes());
+        let attendu: HashMap<String, usize> = [
+            ("Document", 1),
+            ("Document/Dossier", 1),
+            ("Document/Dossier/@type", 1),
+            ("Document/Dossier/@numero", 1),
+            ("Document/Dossier/Code_Juridiction", 1),
+            ("TA/Document/Dossier/Avocat", 2),
+            ("TA/Document/Dossier/Avocat/@qualite", 1),
+            ("TA/Document/Decision", 1),
+        ]
+        .into_iter()
+        .map(|(chemin, nombre)| (chemin.to_string(), nombre))
+        .collect();
+        assert_eq!(compteur, attendu);
+    }
+}
@@ -0,0 +1,633 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const TAILLE_LOT_DOCUMENTS_DEFAUT: usize = 5_000;
+pub const TAILLE_LOT_OCTETS_DEFAUT: usize = 10 * 1024 * 1024;
+pub const REQUETES_SIMULTANEES_DEFAUT: usize = 4;
+pub const TENTATIVES_MAX_DEFAUT: usize = 5;
+pub const DELAI_INITIAL_MS_DEFAUT: usize = 500;
+pub const LETTRE_MORTE_DEFAUT: &str = "lettre_morte.ndjson";
+pub const REJETS_DEFAUT: &str = "rejets.ndjson";
+pub const QUALITE_DEFAUT: &str = "qualite";
+pub const EXEMPLES_PROFIL_DEFAUT: usize = 3;
+pub const ROUTING_DEFAUT: &str = "juridiction";
+pub const MODELE_INDEX_DEFAUT: &str = "{juridiction}_decisions";
+pub const NOM_MODELE_DEFAUT: &str = "decisions";
+pub const SHARDS_DEFAUT: usize = 6;
+pub const REPLICAS_DEFAUT: usize = 0;
+
+#[derive(Clone)]
+pub struct ConfigurationElastic {
+    pub url: String,
+    pub taille_lot_documents: usize,
+    pub taille_lot_octets: usize,
+    pub requetes_simultanees: usize,
+    pub gzip: bool,
+    pub tentatives_max: usize,
+    pub delai_initial_ms: usize,
+    pub lettre_morte: PathBuf,
+    /// Ingestion bleu/vert : écrit dans un nouvel index horodaté puis y
+    /// bascule cet alias.
+    pub alias: Option<String>,
+    pub supprimer_ancien: bool,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeOperation {
+    Index,
+    Create,
+}
+
+/// Source du numéro de version externe (`version_type: external_gte`).
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceVersion {
+    /// `date_mise_jour` au format `AAAAMMJJ`.
+    Date,
+}
+
+#[derive(Clone)]
+pub struct ConfigurationAction {
+    /// Modèle du nom d'index, par exemple `decisions-{juridiction}-{annee_lecture}`.
+    pub index: String,
+    /// Champ du document utilisé comme clé de routage.
+    pub routing: Option<String>,
+    pub pipeline: Option<String>,
+    pub type_operation: TypeOperation,
+    pub version: Option<SourceVersion>,
+}
+
+#[derive(Clone)]
+pub struct ConfigurationModele {
+    pub nom: String,
+    /// Motifs `index_patterns` ; vide, ils sont déduits du modèle d'index.
+    pub motifs: Vec<String>,
+    pub shards: usize,
+    pub replicas: usize,
+    /// Installe le modèle d'index avant l'ingestion.
+    pub installer: bool,
+}
+
+/// Conditions qui font échouer un run (code de sortie 2).
+#[derive(Clone, Default)]
+pub struct ConfigurationSeuils {
+    /// Échoue en fin de run si un fichier est rejeté ou un document refusé
+    /// par Elasticsearch.
+    pub strict: bool,
+    pub max_rejets: Option<usize>,
+    /// Pourcentage maximal de décisions sans le champ.
+    pub max_manquants: Vec<(String, f64)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FormatProfil {
+    Csv,
+    Json,
+}
+
+/// Sous-commande `profil`.
+#[derive(Clone)]
+pub struct ConfigurationProfil {
+    /// Champs profilés ; vide, ceux de `CHAMPS_PROFIL_DEFAUT` présents dans la
+    /// table des champs.
+    pub champs: Vec<String>,
+    pub format: FormatProfil,
+    /// Identifiants cités pour chaque valeur.
+    pub exemples: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Commande {
+    Ingestion,
+    /// Affiche, et installe avec `--installer-modele`, le modèle d'index.
+    Mapping,
+    /// Valeurs distinctes des champs, sans produire de fichier bulk.
+    Profil,
+    /// Applique les règles de `solution_normalisee` à un corpus et liste les
+    /// solutions non reconnues.
+    Solutions,
+}
+
+pub struct Configuration {
+    pub commande: Commande,
+    pub dossiers: Vec<String>,
+    pub sortie: Option<PathBuf>,
+    pub elastic: Option<ConfigurationElastic>,
+    pub action: ConfigurationAction,
+    pub modele: ConfigurationModele,
+    /// Fichier d'état de l'ingestion incrémentale.
+    pub etat: Option<PathBuf>,
+    /// Correspondance balises → champs remplaçant celle intégrée.
+    pub champs: Option<PathBuf>,
+    /// Règles de `solution_normalisee` remplaçant celles intégrées.
+    pub solutions: Option<PathBuf>,
+    /// Conserve les balises hors table des champs dans `extra`.
+    pub extra: bool,
+    /// Conserve les champs lus d'un fichier mal formé au lieu de le rejeter.
+    pub recuperation: bool,
+    /// Rapport des fichiers n'ayant produit aucune décision.
+    pub rejets: PathBuf,
+    /// Dossier des rapports de qualité, un par run.
+    pub qualite: PathBuf,
+    /// Dossier où sont copiés les fichiers rejetés.
+    pub quarantaine: Option<PathBuf>,
+    /// Liens physiques plutôt que copies en quarantaine.
+    pub quarantaine_lien: bool,
+    pub seuils: ConfigurationSeuils,
+    pub profil: ConfigurationProfil,
+}
+
+/// Fichier TOML passé par `--config` ; les options de la ligne de commande
+/// l'emportent sur ses valeurs.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FichierConfiguration {
+    elasticsearch: SectionElasticsearch,
+    action: SectionAction,
+    modele: SectionModele,
+    seuils: SectionSeuils,
+    etat: Option<PathBuf>,
+    champs: Option<PathBuf>,
+    solutions: Option<PathBuf>,
+    extra: Option<bool>,
+    recuperation: Option<bool>,
+    rejets: Option<PathBuf>,
+    qualite: Option<PathBuf>,
+    quarantaine: SectionQuarantaine,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SectionElasticsearch {
+    url: Option<String>,
+    taille_lot: Option<usize>,
+    taille_lot_octets: Option<usize>,
+    requetes_simultanees: Option<usize>,
+    gzip: Option<bool>,
+    tentatives_max: Option<usize>,
+    delai_initial_ms: Option<usize>,
+    lettre_morte: Option<PathBuf>,
+    alias: Option<String>,
+    supprimer_ancien: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SectionAction {
+    index: Option<String>,
+    routing: Option<String>,
+    pipeline: Option<String>,
+    op_type: Option<TypeOperation>,
+    version_externe: Option<SourceVersion>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SectionModele {
+    nom: Option<String>,
+    motifs: Option<Vec<String>>,
+    shards: Option<usize>,
+    replicas: Option<usize>,
+    installer: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SectionQuarantaine {
+    dossier: Option<PathBuf>,
+    lien: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SectionSeuils {
+    strict: Option<bool>,
+    max_rejets: Option<usize>,
+    /// Champ → pourcentage maximal de décisions sans ce champ.
+    max_manquants: BTreeMap<String, f64>,
+}
+
+fn charger_fichier_configuration(chemin: &Path) -> Result<FichierConfiguration, String> {
+    let contenu = std::fs::read_to_string(chemin)
+        .map_err(|e| format!("lecture de {} impossible: {}", chemin.display(), e))?;
+    toml::from_str(&contenu).map_err(|e| format!("{}: {}", chemin.display(), e))
+}
+
+/// `texte_integral=5` : au plus 5 % de décisions sans `texte_integral`.
+fn seuil_manquants(option: &str, valeur: &str) -> Result<(String, f64), String> {
+    let erreur = || {
+        format!(
+            "l'option {} attend CHAMP=POURCENTAGE entre 0 et 100, reçu « {} »",
+            option, valeur
+        )
+    };
+    let (champ, pourcentage) = valeur.split_once('=').ok_or_else(erreur)?;
+    match pourcentage.trim().parse::<f64>() {
+        Ok(p) if !champ.is_empty() && (0.0..=100.0).contains(&p) => Ok((champ.to_string(), p)),
+        _ => Err(erreur()),
+    }
+}
+
+fn routing_depuis(champ: String) -> Option<String> {
+    (champ != "aucun").then_some(champ)
+}
+
+fn valeur_option(option: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("l'option {} attend une valeur", option))
+}
+
+/// Contrôle commun aux entiers de la ligne de commande et du fichier
+/// `--config` qui ne peuvent pas être nuls.
+fn entier_positif(nom: &str, valeur: usize) -> Result<usize, String> {
+    if valeur > 0 {
+        Ok(valeur)
+    } else {
+        Err(format!(
+            "{} attend un entier positif, reçu « {} »",
+            nom, valeur
+        ))
+    }
+}
+
+/// Valeur du fichier `--config`, sinon la valeur par défaut.
+fn entier_positif_fichier(
+    nom: &str,
+    valeur: Option<usize>,
+    defaut: usize,
+) -> Result<usize, String> {
+    match valeur {
+        Some(valeur) => entier_positif(nom, valeur),
+        None => Ok(defaut),
+    }
+}
+
+fn valeur_entiere(option: &str, args: &mut impl Iterator<Item = String>) -> Result<usize, String> {
+    let valeur = valeur_option(option, args)?;
+    let nom = format!("l'option {}", option);
+    match valeur.parse::<usize>() {
+        Ok(n) => entier_positif(&nom, n),
+        Err(_) => Err(format!(
+            "{} attend un entier positif, reçu « {} »",
+            nom, valeur
+        )),
+    }
+}
+
+fn valeur_naturelle(
+    option: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<usize, String> {
+    let valeur = valeur_option(option, args)?;
+    valeur
+        .parse::<usize>()
+        .map_err(|_| format!("l'option {} attend un entier, reçu « {} »", option, valeur))
+}
+
+/// Un premier argument `mapping`, `profil` ou `solutions` sélectionne la
+/// sous-commande correspondante ; `profil` et `solutions` écrivent sur la
+/// sortie standard.
+/// Sans `--es`, le dernier argument positionnel est le fichier bulk de sortie.
+/// Avec `--es`, tous les arguments positionnels sont des dossiers à analyser.
+pub fn parser_arguments(arguments: impl Iterator<Item = String>) -> Result<Configuration, String> {
+    let arguments: Vec<String> = arguments.collect();
+    let fichier = match arguments.iter().position(|arg| arg == "--config") {
+        Some(position) => {
+            let chemin = arguments
+                .get(position + 1)
+                .ok_or("l'option --config attend une valeur")?;
+            charger_fichier_configuration(Path::new(chemin))?
+        }
+        None => FichierConfiguration::default(),
+    };
+    let elasticsearch = fichier.elasticsearch;
+    let mut positionnels = Vec::new();
+    let mut url = elasticsearch.url;
+    let mut taille_lot_documents = entier_positif_fichier(
+        "elasticsearch.taille_lot",
+        elasticsearch.taille_lot,
+        TAILLE_LOT_DOCUMENTS_DEFAUT,
+    )?;
+    let mut taille_lot_octets = entier_positif_fichier(
+        "elasticsearch.taille_lot_octets",
+        elasticsearch.taille_lot_octets,
+        TAILLE_LOT_OCTETS_DEFAUT,
+    )?;
+    let mut requetes_simultanees = entier_positif_fichier(
+        "elasticsearch.requetes_simultanees",
+        elasticsearch.requetes_simultanees,
+        REQUETES_SIMULTANEES_DEFAUT,
+    )?;
+    let mut gzip = elasticsearch.gzip.unwrap_or(false);
+    let mut tentatives_max = entier_positif_fichier(
+        "elasticsearch.tentatives_max",
+        elasticsearch.tentatives_max,
+        TENTATIVES_MAX_DEFAUT,
+    )?;
+    let mut delai_initial_ms = entier_positif_fichier(
+        "elasticsearch.delai_initial_ms",
+        elasticsearch.delai_initial_ms,
+        DELAI_INITIAL_MS_DEFAUT,
+    )?;
+    let mut lettre_morte = elasticsearch
+        .lettre_morte
+        .unwrap_or_else(|| PathBuf::from(LETTRE_MORTE_DEFAUT));
+    let mut alias = elasticsearch.alias;
+    let mut supprimer_ancien = elasticsearch.supprimer_ancien.unwrap_or(false);
+    let mut action = ConfigurationAction {
+        index: fichier
+            .action
+            .index
+            .unwrap_or_else(|| MODELE_INDEX_DEFAUT.to_string()),
+        routing: match fichier.action.routing {
+            Some(champ) => routing_depuis(champ),
+            None => Some(ROUTING_DEFAUT.to_string()),
+        },
+        pipeline: fichier.action.pipeline,
+        type_operation: fichier.action.op_type.unwrap_or(TypeOperation::Index),
+        version: fichier.action.version_externe,
+    };
+    let mut modele = ConfigurationModele {
+        nom: fichier
+            .modele
+            .nom
+            .unwrap_or_else(|| NOM_MODELE_DEFAUT.to_string()),
+        motifs: fichier.modele.motifs.unwrap_or_default(),
+        shards: entier_positif_fichier("modele.shards", fichier.modele.shards, SHARDS_DEFAUT)?,
+        replicas: fichier.modele.replicas.unwrap_or(REPLICAS_DEFAUT),
+        installer: fichier.modele.installer.unwrap_or(false),
+    };
+    let mut etat = fichier.etat;
+    let mut champs = fichier.champs;
+    let mut solutions = fichier.solutions;
+    let mut extra = fichier.extra.unwrap_or(false);
+    let mut recuperation = fichier.recuperation.unwrap_or(false);
+    let mut rejets = fichier
+        .rejets
+        .unwrap_or_else(|| PathBuf::from(REJETS_DEFAUT));
+    let mut qualite = fichier
+        .qualite
+        .unwrap_or_else(|| PathBuf::from(QUALITE_DEFAUT));
+    let mut quarantaine = fichier.quarantaine.dossier;
+    let mut quarantaine_lien = fichier.quarantaine.lien.unwrap_or(false);
+    let mut seuils = ConfigurationSeuils {
+        strict: fichier.seuils.strict.unwrap_or(false),
+        max_rejets: fichier.seuils.max_rejets,
+        max_manquants: Vec::new(),
+    };
+    for (champ, pourcentage) in fichier.seuils.max_manquants {
+        if !(0.0..=100.0).contains(&pourcentage) {
+            return Err(format!(
+                "seuils.max_manquants.{} doit être compris entre 0 et 100",
+                champ
+            ));
+        }
+        seuils.max_manquants.push((champ, pourcentage));
+    }
+    let mut profil = ConfigurationProfil {
+        champs: Vec::new(),
+        format: FormatProfil::Csv,
+        exemples: EXEMPLES_PROFIL_DEFAUT,
+    };
+    let mut args = arguments.into_iter().peekable();
+    let commande = match args.peek().map(String::as_str) {
+        Some("mapping") => {
+            args.next();
+            Commande::Mapping
+        }
+        Some("profil") => {
+            args.next();
+            Commande::Profil
+        }
+        Some("solutions") => {
+            args.next();
+            Commande::Solutions
+        }
+        _ => Commande::Ingestion,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                valeur_option(&arg, &mut args)?;
+            }
+            "--es" => url = Some(valeur_option(&arg, &mut args)?),
+            "--taille-lot" => taille_lot_documents = valeur_entiere(&arg, &mut args)?,
+            "--taille-lot-octets" => taille_lot_octets = valeur_entiere(&arg, &mut args)?,
+            "--requetes-simultanees" => requetes_simultanees = valeur_entiere(&arg, &mut args)?,
+            "--gzip" => gzip = true,
+            "--tentatives-max" => tentatives_max = valeur_entiere(&arg, &mut args)?,
+            "--delai-initial-ms" => delai_initial_ms = valeur_entiere(&arg, &mut args)?,
+            "--lettre-morte" => lettre_morte = PathBuf::from(valeur_option(&arg, &mut args)?),
+            "--index" => action.index = valeur_option(&arg, &mut args)?,
+            "--routing" => action.routing = routing_depuis(valeur_option(&arg, &mut args)?),
+            "--pipeline" => action.pipeline = Some(valeur_option(&arg, &mut args)?),
+            "--op-type" => {
+                action.type_operation = match valeur_option(&arg, &mut args)?.as_str() {
+                    "index" => TypeOperation::Index,
+                    "create" => TypeOperation::Create,
+                    autre => {
+                        return Err(format!(
+                            "l'option --op-type attend index ou create, reçu « {} »",
+                            autre
+                        ));
+                    }
+                }
+            }
+            "--etat" => etat = Some(PathBuf::from(valeur_option(&arg, &mut args)?)),
+            "--champs" => champs = Some(PathBuf::from(valeur_option(&arg, &mut args)?)),
+            "--solutions" => solutions = Some(PathBuf::from(valeur_option(&arg, &mut args)?)),
+            "--extra" => extra = true,
+            "--recuperation" => recuperation = true,
+            "--rejets" => rejets = PathBuf::from(valeur_option(&arg, &mut args)?),
+            "--qualite" => qualite = PathBuf::from(valeur_option(&arg, &mut args)?),
+            "--quarantaine" => quarantaine = Some(PathBuf::from(valeur_option(&arg, &mut args)?)),
+            "--quarantaine-lien" => quarantaine_lien = true,
+            "--strict" => seuils.strict = true,
+            "--max-rejets" => seuils.max_rejets = Some(valeur_naturelle(&arg, &mut args)?),
+            "--max-manquants" => {
+                let valeur = valeur_option(&arg, &mut args)?;
+                seuils.max_manquants.push(seuil_manquants(&arg, &valeur)?);
+            }
+            "--alias" => alias = Some(valeur_option(&arg, &mut args)?),
+            "--supprimer-ancien" => supprimer_ancien = true,
+            "--version-externe" => {
+                action.version = match valeur_option(&arg, &mut args)?.as_str() {
+                    "date" => Some(SourceVersion::Date),
+                    autre => {
+                        return Err(format!(
+                            "l'option --version-externe attend date, reçu « {} »",
+                            autre
+                        ));
+                    }
+                }
+            }
+            "--nom-modele" => modele.nom = valeur_option(&arg, &mut args)?,
+            "--motif-index" => modele.motifs.push(valeur_option(&arg, &mut args)?),
+            "--shards" => modele.shards = valeur_entiere(&arg, &mut args)?,
+            "--replicas" => modele.replicas = valeur_naturelle(&arg, &mut args)?,
+            "--installer-modele" => modele.installer = true,
+            "--champ" => profil.champs.push(valeur_option(&arg, &mut args)?),
+            "--format" => {
+                profil.format = match valeur_option(&arg, &mut args)?.as_str() {
+                    "csv" => FormatProfil::Csv,
+                    "json" => FormatProfil::Json,
+                    autre => {
+                        return Err(format!(
+                            "l'option --format attend csv ou json, reçu « {} »",
+                            autre
+                        ));
+                    }
+                }
+            }
+            "--exemples" => profil.exemples = valeur_naturelle(&arg, &mut args)?,
+            _ if arg.starts_with("--") => return Err(format!("option inconnue: {}", arg)),
+            _ => positionnels.push(arg),
+        }
+    }
+    let sortie = if commande == Commande::Mapping {
+        if !positionnels.is_empty() {
+            return Err("la commande mapping n'attend aucun dossier".to_string());
+        }
+        None
+    } else if matches!(commande, Commande::Profil | Commande::Solutions) {
+        None
+    } else if url.is_none() {
+        if positionnels.len() < 2 {
+            return Err("au moins un dossier et un fichier de sortie sont requis".to_string());
+        }
+        positionnels.pop().map(PathBuf::from)
+    } else {
+        None
+    };
+    if commande != Commande::Mapping && positionnels.is_empty() {
+        return Err("au moins un dossier est requis".to_string());
+    }
+    if modele.installer && url.is_none() {
+        return Err("l'option --installer-modele nécessite --es".to_string());
+    }
+    if alias.is_some() && url.is_none() {
+        return Err("l'option --alias nécessite --es".to_string());
+    }
+    if action.version.is_some() && matches!(action.type_operation, TypeOperation::Create) {
+        return Err(
+            "l'option --version-externe est incompatible avec --op-type create".to_string(),
+        );
+    }
+    if etat.is_some() && alias.is_some() {
+        return Err("l'option --etat est incompatible avec --alias".to_string());
+    }
+    if quarantaine_lien && quarantaine.is_none() {
+        return Err("l'option --quarantaine-lien nécessite --quarantaine".to_string());
+    }
+    if supprimer_ancien && alias.is_none() {
+        return Err("l'option --supprimer-ancien nécessite --alias".to_string());
+    }
+    Ok(Configuration {
+        commande,
+        dossiers: positionnels,
+        sortie,
+        elastic: url.map(|url| ConfigurationElastic {
+            url,
+            taille_lot_documents,
+            taille_lot_octets,
+            requetes_simultanees,
+            gzip,
+            tentatives_max,
+            delai_initial_ms,
+            lettre_morte,
+            alias,
+            supprimer_ancien,
+        }),
+        action,
+        modele,
+        etat,
+        champs,
+        solutions,
+        extra,
+        recuperation,
+        rejets,
+        qualite,
+        quarantaine,
+        quarantaine_lien,
+        seuils,
+        profil,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(arguments: &[&str]) -> Result<Configuration, String> {
+        parser_arguments(arguments.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn refuse_les_entiers_nuls_en_ligne_de_commande_et_dans_le_fichier() {
+        assert!(arguments(&["--es", "http://es", "--requetes-simultanees", "0", "TA"]).is_err());
+        assert!(arguments(&["--es", "http://es", "--taille-lot", "-1", "TA"]).is_err());
+        for section in [
+            "[elasticsearch]\ntaille_lot = 0",
+            "[elasticsearch]\ntaille_lot_octets = 0",
+            "[elasticsearch]\nrequetes_simultanees = 0",
+            "[elasticsearch]\ntentatives_max = 0",
+            "[elasticsearch]\ndelai_initial_ms = 0",
+            "[modele]\nshards = 0",
+        ] {
+            let chemin = std::env::temp_dir().join(format!(
+                "xml_to_elastic_{}_configuration.toml",
+                std::process::id()
+            ));
+            std::fs::write(&chemin, section).unwrap();
+            let resultat = arguments(&[
+                "--config",
+                chemin.to_str().unwrap(),
+                "--es",
+                "http://es",
+                "TA",
+            ]);
+            std::fs::remove_file(&chemin).unwrap();
+            let erreur = resultat.err().unwrap_or_default();
+            assert!(erreur.contains("entier positif"), "{}: {}", section, erreur);
+        }
+    }
+
+    #[test]
+    fn n_accepte_que_la_date_comme_version_externe() {
+        let configuration =
+            arguments(&["--version-externe", "date", "TA", "sortie.ndjson"]).unwrap();
+        assert!(matches!(
+            configuration.action.version,
+            Some(SourceVersion::Date)
+        ));
+        assert!(arguments(&["--version-externe", "empreinte", "TA", "sortie.ndjson"]).is_err());
+    }
+
+    #[test]
+    fn lit_les_entiers_du_fichier() {
+        let chemin = std::env::temp_dir().join(format!(
+            "xml_to_elastic_{}_configuration_valide.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &chemin,
+            "[elasticsearch]\nrequetes_simultanees = 2\ntaille_lot = 10\n[modele]\nreplicas = 0\n",
+        )
+        .unwrap();
+        let configuration = arguments(&[
+            "--config",
+            chemin.to_str().unwrap(),
+            "--es",
+            "http://es",
+            "TA",
+        ]);
+        std::fs::remove_file(&chemin).unwrap();
+        let configuration = configuration.unwrap();
+        let elastic = configuration.elastic.unwrap();
+        assert_eq!(elastic.requetes_simultanees, 2);
+        assert_eq!(elastic.taille_lot_documents, 10);
+        assert_eq!(configuration.modele.replicas, 0);
+    }
+}
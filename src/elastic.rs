@@ -0,0 +1,913 @@
+use crate::configuration::ConfigurationElastic;
+
+use crossbeam_channel::{Receiver, SendError, Sender, bounded};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use owo_colors::OwoColorize;
+use serde_json::{Value, json};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const DELAI_REQUETE: Duration = Duration::from_secs(120);
+const DELAI_MAXIMAL: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct BilanEnvoi {
+    pub lots_envoyes: usize,
+    pub documents_envoyes: usize,
+    pub documents_indexes: usize,
+    pub documents_reessayes: usize,
+    /// Conflits de version (409) sur une action `external_gte` : une version
+    /// plus récente est déjà indexée.
+    pub documents_ignores: usize,
+    pub documents_rejetes: usize,
+    /// Documents rejetés qui n'ont pas pu être écrits en lettre morte.
+    pub lettre_morte_en_echec: usize,
+}
+
+impl BilanEnvoi {
+    fn fusionner(&mut self, autre: BilanEnvoi) {
+        self.lots_envoyes += autre.lots_envoyes;
+        self.documents_envoyes += autre.documents_envoyes;
+        self.documents_indexes += autre.documents_indexes;
+        self.documents_reessayes += autre.documents_reessayes;
+        self.documents_ignores += autre.documents_ignores;
+        self.documents_rejetes += autre.documents_rejetes;
+        self.lettre_morte_en_echec += autre.lettre_morte_en_echec;
+    }
+}
+
+struct ElementLot {
+    action: String,
+    /// Absent pour une action `delete`.
+    document: Option<String>,
+    source: String,
+}
+
+#[derive(Default)]
+struct Lot {
+    elements: Vec<ElementLot>,
+    octets: usize,
+}
+
+fn construire_corps(elements: &[ElementLot]) -> Vec<u8> {
+    let mut corps = Vec::new();
+    for element in elements {
+        corps.extend_from_slice(element.action.as_bytes());
+        corps.push(b'\n');
+        if let Some(document) = &element.document {
+            corps.extend_from_slice(document.as_bytes());
+            corps.push(b'\n');
+        }
+    }
+    corps
+}
+
+/// Fichier NDJSON des documents définitivement refusés, créé au premier rejet.
+/// Chaque ligne conserve l'action et le document d'origine afin de pouvoir
+/// être rejouée ; elle est vidée sur disque aussitôt écrite, les rejets étant
+/// rares.
+struct LettreMorte {
+    chemin: PathBuf,
+    fichier: Mutex<Option<BufWriter<File>>>,
+    /// Lignes qui n'ont pas pu être écrites.
+    echecs: AtomicUsize,
+}
+
+impl LettreMorte {
+    fn new(chemin: PathBuf) -> Self {
+        LettreMorte {
+            chemin,
+            fichier: Mutex::new(None),
+            echecs: AtomicUsize::new(0),
+        }
+    }
+
+    fn ecrire(&self, element: &ElementLot, statut: Option<u16>, erreur: &Value) {
+        let ligne = json!({
+            "source": element.source,
+            "statut": statut,
+            "erreur": erreur,
+            "action": serde_json::from_str::<Value>(&element.action).unwrap_or(Value::Null),
+            "document": element
+                .document
+                .as_deref()
+                .and_then(|document| serde_json::from_str::<Value>(document).ok()),
+        });
+        let mut fichier = self.fichier.lock().unwrap();
+        let resultat = match fichier.as_mut() {
+            Some(f) => Ok(f),
+            None => File::create(&self.chemin).map(|f| fichier.insert(BufWriter::new(f))),
+        }
+        .and_then(|f| {
+            writeln!(f, "{}", ligne)?;
+            f.flush()
+        });
+        if let Err(e) = resultat
+            && self.echecs.fetch_add(1, Ordering::Relaxed) == 0
+        {
+            eprintln!(
+                "{} {} {}",
+                "Erreur lettre morte:".red().bold(),
+                self.chemin.display(),
+                e.to_string().red()
+            );
+        }
+    }
+
+    fn echecs(&self) -> usize {
+        self.echecs.load(Ordering::Relaxed)
+    }
+}
+
+/// Regroupe les paires action/document en lots NDJSON et les transmet à
+/// l'API `_bulk` depuis un groupe de threads, au plus `requetes_simultanees`
+/// requêtes étant en vol à un instant donné.
+pub struct ClientBulk {
+    taille_lot_documents: usize,
+    taille_lot_octets: usize,
+    lot: Lot,
+    envoyeur: Arc<Envoyeur>,
+    requetes_simultanees: usize,
+    emetteur: Sender<Lot>,
+    travailleurs: Vec<JoinHandle<BilanEnvoi>>,
+    /// Bilan des threads d'envoi arrêtés par `attendre`.
+    bilan: BilanEnvoi,
+    lettre_morte: Arc<LettreMorte>,
+    /// Documents qu'aucun thread d'envoi n'a pu prendre en charge.
+    non_transmis: usize,
+}
+
+fn demarrer(
+    envoyeur: &Arc<Envoyeur>,
+    requetes_simultanees: usize,
+) -> (Sender<Lot>, Vec<JoinHandle<BilanEnvoi>>) {
+    let (emetteur, recepteur) = bounded::<Lot>(requetes_simultanees);
+    let travailleurs = (0..requetes_simultanees)
+        .map(|_| {
+            let recepteur = recepteur.clone();
+            let envoyeur = envoyeur.clone();
+            std::thread::spawn(move || envoyeur.envoyer_lots(recepteur))
+        })
+        .collect();
+    (emetteur, travailleurs)
+}
+
+impl ClientBulk {
+    pub fn new(configuration: &ConfigurationElastic) -> Self {
+        let lettre_morte = Arc::new(LettreMorte::new(configuration.lettre_morte.clone()));
+        let envoyeur = Arc::new(Envoyeur {
+            agent: ureq::AgentBuilder::new().timeout(DELAI_REQUETE).build(),
+            url: format!("{}/_bulk", configuration.url.trim_end_matches('/')),
+            gzip: configuration.gzip,
+            tentatives_max: configuration.tentatives_max,
+            delai_initial: Duration::from_millis(configuration.delai_initial_ms as u64),
+            lettre_morte: lettre_morte.clone(),
+        });
+        let (emetteur, travailleurs) = demarrer(&envoyeur, configuration.requetes_simultanees);
+        ClientBulk {
+            taille_lot_documents: configuration.taille_lot_documents,
+            taille_lot_octets: configuration.taille_lot_octets,
+            lot: Lot::default(),
+            envoyeur,
+            requetes_simultanees: configuration.requetes_simultanees,
+            emetteur,
+            travailleurs,
+            bilan: BilanEnvoi::default(),
+            lettre_morte,
+            non_transmis: 0,
+        }
+    }
+
+    pub fn ajouter(&mut self, action: &str, document: Option<&str>, source: &str) {
+        let taille = action.len() + document.map(|d| d.len() + 1).unwrap_or(0) + 1;
+        if !self.lot.elements.is_empty()
+            && (self.lot.elements.len() >= self.taille_lot_documents
+                || self.lot.octets + taille > self.taille_lot_octets)
+        {
+            self.vider();
+        }
+        self.lot.elements.push(ElementLot {
+            action: action.to_string(),
+            document: document.map(str::to_string),
+            source: source.to_string(),
+        });
+        self.lot.octets += taille;
+    }
+
+    /// Transmet le lot en cours aux threads d'envoi. S'ils se sont tous
+    /// arrêtés, les documents vont directement en lettre morte.
+    fn vider(&mut self) {
+        let lot = std::mem::take(&mut self.lot);
+        if let Err(SendError(lot)) = self.emetteur.send(lot) {
+            let message = "aucun thread d'envoi actif";
+            if self.non_transmis == 0 {
+                eprintln!("{} {}", "Erreur bulk:".red().bold(), message.red());
+            }
+            let erreur = Value::String(message.to_string());
+            for element in &lot.elements {
+                self.lettre_morte.ecrire(element, None, &erreur);
+            }
+            self.non_transmis += lot.elements.len();
+        }
+    }
+
+    /// Envoie le lot en cours et attend que tous les lots transmis aient été
+    /// traités, renvois compris : les éléments ajoutés ensuite partent dans
+    /// des requêtes ultérieures.
+    pub fn attendre(&mut self) {
+        if !self.lot.elements.is_empty() {
+            self.vider();
+        }
+        let (emetteur, travailleurs) = demarrer(&self.envoyeur, self.requetes_simultanees);
+        drop(std::mem::replace(&mut self.emetteur, emetteur));
+        for travailleur in std::mem::replace(&mut self.travailleurs, travailleurs) {
+            self.bilan.fusionner(travailleur.join().unwrap());
+        }
+    }
+
+    pub fn terminer(mut self) -> BilanEnvoi {
+        if !self.lot.elements.is_empty() {
+            self.vider();
+        }
+        drop(self.emetteur);
+        let mut bilan = self.bilan;
+        bilan.documents_envoyes += self.non_transmis;
+        bilan.documents_rejetes += self.non_transmis;
+        for travailleur in self.travailleurs {
+            bilan.fusionner(travailleur.join().unwrap());
+        }
+        bilan.lettre_morte_en_echec = self.lettre_morte.echecs();
+        bilan
+    }
+}
+
+enum ErreurEnvoi {
+    /// Erreur réseau, 429 ou 5xx sur la requête entière : le lot est renvoyé.
+    Temporaire(String),
+    /// Requête refusée dans son ensemble (4xx) : inutile de la renvoyer.
+    Definitive(Option<u16>, String),
+    /// 413 : la requête dépasse la taille acceptée par Elasticsearch, les
+    /// documents eux-mêmes peuvent être valides.
+    TropVolumineuse(String),
+}
+
+struct ResultatElement {
+    statut: Option<u16>,
+    erreur: Value,
+}
+
+fn est_temporaire(statut: u16) -> bool {
+    statut == 429 || statut >= 500
+}
+
+/// Seul un conflit sur une action `external_gte` signifie qu'une version
+/// plus récente est déjà indexée ; les autres conflits sont des rejets.
+fn est_versionnee(action: &str) -> bool {
+    serde_json::from_str::<Value>(action)
+        .ok()
+        .and_then(|action| {
+            action
+                .as_object()
+                .and_then(|operation| operation.values().next())
+                .map(|meta| meta["version_type"] == "external_gte")
+        })
+        .unwrap_or(false)
+}
+
+struct Envoyeur {
+    agent: ureq::Agent,
+    url: String,
+    gzip: bool,
+    tentatives_max: usize,
+    delai_initial: Duration,
+    lettre_morte: Arc<LettreMorte>,
+}
+
+impl Envoyeur {
+    fn envoyer_lots(&self, recepteur: Receiver<Lot>) -> BilanEnvoi {
+        let mut bilan = BilanEnvoi::default();
+        for lot in recepteur {
+            bilan.lots_envoyes += 1;
+            bilan.documents_envoyes += lot.elements.len();
+            self.traiter_lot(lot.elements, &mut bilan);
+        }
+        bilan
+    }
+
+    fn delai(&self, tentative: usize) -> Duration {
+        let facteur = 1u32 << (tentative - 1).min(16);
+        (self.delai_initial * facteur).min(DELAI_MAXIMAL)
+    }
+
+    /// Envoie les éléments, renvoie avec un délai exponentiel ceux qui ont
+    /// échoué temporairement et place en lettre morte ceux qui sont refusés
+    /// définitivement ou qui ont épuisé leurs tentatives.
+    fn traiter_lot(&self, elements: Vec<ElementLot>, bilan: &mut BilanEnvoi) {
+        let mut restants = elements;
+        let mut tentative = 0;
+        loop {
+            let mut echecs = Vec::new();
+            match self.envoyer(&restants) {
+                Ok(resultats) => {
+                    for (element, resultat) in restants.into_iter().zip(resultats) {
+                        match resultat.statut {
+                            Some(200..=299) => bilan.documents_indexes += 1,
+                            Some(409) if est_versionnee(&element.action) => {
+                                bilan.documents_ignores += 1
+                            }
+                            Some(statut) if est_temporaire(statut) => {
+                                echecs.push((element, resultat))
+                            }
+                            _ => {
+                                self.lettre_morte.ecrire(
+                                    &element,
+                                    resultat.statut,
+                                    &resultat.erreur,
+                                );
+                                bilan.documents_rejetes += 1;
+                            }
+                        }
+                    }
+                }
+                Err(ErreurEnvoi::Temporaire(message)) => {
+                    eprintln!("{} {}", "Erreur bulk:".yellow().bold(), message.yellow());
+                    echecs = restants
+                        .into_iter()
+                        .map(|element| {
+                            let resultat = ResultatElement {
+                                statut: None,
+                                erreur: Value::String(message.clone()),
+                            };
+                            (element, resultat)
+                        })
+                        .collect();
+                }
+                Err(ErreurEnvoi::TropVolumineuse(message)) if restants.len() > 1 => {
+                    eprintln!(
+                        "{} {} {}",
+                        "Lot trop volumineux:".yellow().bold(),
+                        message.yellow(),
+                        "(découpé en deux, réduire --taille-lot-octets)".yellow()
+                    );
+                    let seconde_moitie = restants.split_off(restants.len() / 2);
+                    self.traiter_lot(restants, bilan);
+                    self.traiter_lot(seconde_moitie, bilan);
+                    return;
+                }
+                Err(ErreurEnvoi::TropVolumineuse(message)) => {
+                    let message = format!("{} (document seul plus gros que la limite)", message);
+                    self.rejeter(&restants, Some(413), message, bilan);
+                    return;
+                }
+                Err(ErreurEnvoi::Definitive(statut, message)) => {
+                    self.rejeter(&restants, statut, message, bilan);
+                    return;
+                }
+            }
+            if echecs.is_empty() {
+                return;
+            }
+            if tentative >= self.tentatives_max {
+                for (element, resultat) in &echecs {
+                    self.lettre_morte
+                        .ecrire(element, resultat.statut, &resultat.erreur);
+                }
+                bilan.documents_rejetes += echecs.len();
+                return;
+            }
+            tentative += 1;
+            bilan.documents_reessayes += echecs.len();
+            std::thread::sleep(self.delai(tentative));
+            restants = echecs.into_iter().map(|(element, _)| element).collect();
+        }
+    }
+
+    /// Place en lettre morte tous les éléments d'une requête refusée.
+    fn rejeter(
+        &self,
+        elements: &[ElementLot],
+        statut: Option<u16>,
+        message: String,
+        bilan: &mut BilanEnvoi,
+    ) {
+        eprintln!("{} {}", "Erreur bulk:".red().bold(), message.red());
+        let erreur = Value::String(message);
+        for element in elements {
+            self.lettre_morte.ecrire(element, statut, &erreur);
+        }
+        bilan.documents_rejetes += elements.len();
+    }
+
+    fn envoyer(&self, elements: &[ElementLot]) -> Result<Vec<ResultatElement>, ErreurEnvoi> {
+        let corps = construire_corps(elements);
+        let requete = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/x-ndjson");
+        let resultat = if self.gzip {
+            let compresse =
+                compresser(&corps).map_err(|e| ErreurEnvoi::Definitive(None, e.to_string()))?;
+            requete
+                .set("Content-Encoding", "gzip")
+                .send_bytes(&compresse)
+        } else {
+            requete.send_bytes(&corps)
+        };
+        let reponse = match resultat {
+            Ok(reponse) => reponse,
+            Err(ureq::Error::Status(code, reponse)) => {
+                let message = format!(
+                    "HTTP {}: {}",
+                    code,
+                    reponse.into_string().unwrap_or_default()
+                );
+                return Err(if est_temporaire(code) {
+                    ErreurEnvoi::Temporaire(message)
+                } else if code == 413 {
+                    ErreurEnvoi::TropVolumineuse(message)
+                } else {
+                    ErreurEnvoi::Definitive(Some(code), message)
+                });
+            }
+            Err(e) => return Err(ErreurEnvoi::Temporaire(e.to_string())),
+        };
+        let texte = reponse
+            .into_string()
+            .map_err(|e| ErreurEnvoi::Temporaire(e.to_string()))?;
+        let json: Value =
+            serde_json::from_str(&texte).map_err(|e| ErreurEnvoi::Temporaire(e.to_string()))?;
+        Ok(analyser_reponse(&json, elements.len()))
+    }
+}
+
+/// Associe à chaque élément envoyé, dans l'ordre, le statut et l'erreur
+/// renvoyés par Elasticsearch.
+fn analyser_reponse(json: &Value, nombre: usize) -> Vec<ResultatElement> {
+    if json["errors"].as_bool() == Some(false) {
+        return (0..nombre)
+            .map(|_| ResultatElement {
+                statut: Some(200),
+                erreur: Value::Null,
+            })
+            .collect();
+    }
+    let items = json["items"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    (0..nombre)
+        .map(|i| {
+            let resultat = items
+                .get(i)
+                .and_then(Value::as_object)
+                .and_then(|item| item.iter().next());
+            match resultat {
+                // Supprimer un document déjà absent n'est pas une erreur.
+                Some((operation, resultat))
+                    if operation == "delete" && resultat["status"].as_u64() == Some(404) =>
+                {
+                    ResultatElement {
+                        statut: Some(200),
+                        erreur: Value::Null,
+                    }
+                }
+                Some((_, resultat)) => ResultatElement {
+                    statut: resultat["status"].as_u64().map(|s| s as u16),
+                    erreur: resultat.get("error").cloned().unwrap_or(Value::Null),
+                },
+                None => ResultatElement {
+                    statut: None,
+                    erreur: Value::String("élément absent de la réponse bulk".to_string()),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Opérations d'administration ponctuelles : modèle d'index, alias, comptage.
+pub struct AdministrationElastic {
+    agent: ureq::Agent,
+    url: String,
+}
+
+impl AdministrationElastic {
+    pub fn new(url: &str) -> Self {
+        AdministrationElastic {
+            agent: ureq::AgentBuilder::new().timeout(DELAI_REQUETE).build(),
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Exécute la requête et retourne le corps JSON de la réponse, ou le
+    /// statut HTTP et le message en cas d'échec.
+    fn executer(
+        &self,
+        methode: &str,
+        chemin: &str,
+        corps: Option<&Value>,
+    ) -> Result<Value, (Option<u16>, String)> {
+        let requete = self
+            .agent
+            .request(methode, &format!("{}/{}", self.url, chemin))
+            .set("Content-Type", "application/json");
+        let resultat = match corps {
+            Some(corps) => requete.send_string(&corps.to_string()),
+            None => requete.call(),
+        };
+        match resultat {
+            Ok(reponse) => {
+                let texte = reponse.into_string().map_err(|e| (None, e.to_string()))?;
+                Ok(serde_json::from_str(&texte).unwrap_or(Value::Null))
+            }
+            Err(ureq::Error::Status(code, reponse)) => Err((
+                Some(code),
+                format!(
+                    "{} {}: HTTP {}: {}",
+                    methode,
+                    chemin,
+                    code,
+                    reponse.into_string().unwrap_or_default()
+                ),
+            )),
+            Err(e) => Err((None, format!("{} {}: {}", methode, chemin, e))),
+        }
+    }
+
+    /// Crée ou remplace le modèle d'index `nom` (`PUT _index_template/<nom>`).
+    pub fn installer_modele_index(&self, nom: &str, modele: &Value) -> Result<(), String> {
+        self.executer("PUT", &format!("_index_template/{}", nom), Some(modele))
+            .map(|_| ())
+            .map_err(|(_, message)| message)
+    }
+
+    pub fn compter(&self, index: &str) -> Result<usize, String> {
+        self.executer("POST", &format!("{}/_refresh", index), None)
+            .map_err(|(_, message)| message)?;
+        let reponse = self
+            .executer("GET", &format!("{}/_count", index), None)
+            .map_err(|(_, message)| message)?;
+        reponse["count"]
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| format!("réponse _count inattendue: {}", reponse))
+    }
+
+    /// Index actuellement désignés par l'alias ; aucun si l'alias n'existe pas.
+    pub fn indices_alias(&self, alias: &str) -> Result<Vec<String>, String> {
+        match self.executer("GET", &format!("_alias/{}", alias), None) {
+            Ok(reponse) => Ok(reponse
+                .as_object()
+                .map(|indices| indices.keys().cloned().collect())
+                .unwrap_or_default()),
+            Err((Some(404), _)) => Ok(Vec::new()),
+            Err((_, message)) => Err(message),
+        }
+    }
+
+    /// Retire l'alias des anciens index et l'ajoute au nouveau en une seule
+    /// requête `_aliases`, donc de façon atomique.
+    pub fn basculer_alias(
+        &self,
+        alias: &str,
+        nouvel_index: &str,
+        anciens: &[String],
+    ) -> Result<(), String> {
+        let mut actions: Vec<Value> = anciens
+            .iter()
+            .filter(|ancien| *ancien != nouvel_index)
+            .map(|ancien| json!({ "remove": { "index": ancien, "alias": alias } }))
+            .collect();
+        actions.push(json!({ "add": { "index": nouvel_index, "alias": alias } }));
+        self.executer("POST", "_aliases", Some(&json!({ "actions": actions })))
+            .map(|_| ())
+            .map_err(|(_, message)| message)
+    }
+
+    pub fn supprimer_index(&self, index: &str) -> Result<(), String> {
+        self.executer("DELETE", index, None)
+            .map(|_| ())
+            .map_err(|(_, message)| message)
+    }
+}
+
+fn compresser(corps: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encodeur = GzEncoder::new(Vec::with_capacity(corps.len() / 4), Compression::fast());
+    encodeur.write_all(corps)?;
+    encodeur.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    struct RequeteRecue {
+        chemin: String,
+        gzip: bool,
+        corps: String,
+    }
+
+    const SUCCES: &str = r#"{"took":1,"errors":false,"items":[]}"#;
+
+    /// Serveur HTTP minimal qui tient lieu d'Elasticsearch : la n-ième requête
+    /// reçoit la n-ième réponse `(statut, corps)`, puis un succès.
+    fn demarrer_serveur(reponses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<RequeteRecue>>>) {
+        let ecoute = TcpListener::bind("127.0.0.1:0").unwrap();
+        let adresse = format!("http://{}", ecoute.local_addr().unwrap());
+        let recues = Arc::new(Mutex::new(Vec::new()));
+        let partage = recues.clone();
+        std::thread::spawn(move || {
+            let mut reponses = reponses.into_iter();
+            for flux in ecoute.incoming() {
+                let mut flux = flux.unwrap();
+                let mut lecteur = BufReader::new(flux.try_clone().unwrap());
+                let mut ligne = String::new();
+                lecteur.read_line(&mut ligne).unwrap();
+                let chemin = ligne.split_whitespace().nth(1).unwrap_or("").to_string();
+                let mut longueur = 0;
+                let mut gzip = false;
+                loop {
+                    let mut entete = String::new();
+                    lecteur.read_line(&mut entete).unwrap();
+                    let entete = entete.trim_end().to_lowercase();
+                    if entete.is_empty() {
+                        break;
+                    }
+                    if let Some(valeur) = entete.strip_prefix("content-length:") {
+                        longueur = valeur.trim().parse().unwrap();
+                    }
+                    if entete == "content-encoding: gzip" {
+                        gzip = true;
+                    }
+                }
+                let mut brut = vec![0; longueur];
+                lecteur.read_exact(&mut brut).unwrap();
+                let mut corps = String::new();
+                if gzip {
+                    GzDecoder::new(&brut[..])
+                        .read_to_string(&mut corps)
+                        .unwrap();
+                } else {
+                    corps = String::from_utf8(brut).unwrap();
+                }
+                partage.lock().unwrap().push(RequeteRecue {
+                    chemin,
+                    gzip,
+                    corps,
+                });
+                let (statut, reponse) = reponses.next().unwrap_or((200, SUCCES.to_string()));
+                write!(
+                    flux,
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    statut,
+                    reponse.len(),
+                    reponse
+                )
+                .unwrap();
+            }
+        });
+        (adresse, recues)
+    }
+
+    fn configuration(url: String, lettre_morte: PathBuf) -> ConfigurationElastic {
+        ConfigurationElastic {
+            url,
+            taille_lot_documents: 1000,
+            taille_lot_octets: 1024 * 1024,
+            requetes_simultanees: 1,
+            gzip: false,
+            tentatives_max: 3,
+            delai_initial_ms: 1,
+            lettre_morte,
+            alias: None,
+            supprimer_ancien: false,
+        }
+    }
+
+    fn chemin_temporaire(nom: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xml_to_elastic_{}_{}", std::process::id(), nom))
+    }
+
+    #[test]
+    fn decoupe_en_lots_et_compresse() {
+        let (url, recues) = demarrer_serveur(Vec::new());
+        let mut client = ClientBulk::new(&ConfigurationElastic {
+            taille_lot_documents: 2,
+            requetes_simultanees: 2,
+            gzip: true,
+            ..configuration(url, chemin_temporaire("lots.ndjson"))
+        });
+        for i in 0..5 {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_index":"decisions","_id":"D{}"}}}}"#, i),
+                Some(&format!(r#"{{"id":"D{}"}}"#, i)),
+                "D.xml",
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.lots_envoyes, 3);
+        assert_eq!(bilan.documents_indexes, 5);
+        assert_eq!(bilan.documents_rejetes, 0);
+        let recues = recues.lock().unwrap();
+        assert_eq!(recues.len(), 3);
+        assert!(recues.iter().all(|r| r.chemin == "/_bulk" && r.gzip));
+        let lignes: usize = recues.iter().map(|r| r.corps.lines().count()).sum();
+        assert_eq!(lignes, 10);
+        assert!(recues.iter().all(|r| r.corps.ends_with('\n')));
+    }
+
+    #[test]
+    fn respecte_la_taille_maximale_en_octets() {
+        let (url, recues) = demarrer_serveur(Vec::new());
+        let mut client = ClientBulk::new(&ConfigurationElastic {
+            taille_lot_octets: 100,
+            ..configuration(url, chemin_temporaire("octets.ndjson"))
+        });
+        let document = format!(r#"{{"texte":"{}"}}"#, "x".repeat(40));
+        for i in 0..4 {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"D{}"}}}}"#, i),
+                Some(&document),
+                "D.xml",
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.lots_envoyes, 4);
+        assert!(recues.lock().unwrap().iter().all(|r| !r.gzip));
+    }
+
+    #[test]
+    fn reessaie_les_elements_temporaires_et_rejette_les_autres() {
+        let premiere = json!({
+            "errors": true,
+            "items": [
+                {"index": {"_id": "A", "status": 201}},
+                {"index": {"_id": "B", "status": 429, "error": {"type": "es_rejected_execution_exception"}}},
+                {"index": {"_id": "C", "status": 400, "error": {"type": "mapper_parsing_exception", "reason": "date invalide"}}}
+            ]
+        });
+        let (url, recues) = demarrer_serveur(vec![
+            (503, "indisponible".to_string()),
+            (200, premiere.to_string()),
+        ]);
+        let lettre_morte = chemin_temporaire("rejets.ndjson");
+        let mut client = ClientBulk::new(&configuration(url, lettre_morte.clone()));
+        for id in ["A", "B", "C"] {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"{}"}}}}"#, id),
+                Some(&format!(r#"{{"id":"{}"}}"#, id)),
+                &format!("TA/{}.xml", id),
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.documents_indexes, 2);
+        assert_eq!(bilan.documents_rejetes, 1);
+        assert_eq!(bilan.documents_reessayes, 4);
+        let recues = recues.lock().unwrap();
+        assert_eq!(recues.len(), 3);
+        assert_eq!(
+            recues[2].corps,
+            "{\"index\":{\"_id\":\"B\"}}\n{\"id\":\"B\"}\n"
+        );
+        let contenu = std::fs::read_to_string(&lettre_morte).unwrap();
+        std::fs::remove_file(&lettre_morte).unwrap();
+        let lignes: Vec<Value> = contenu
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lignes.len(), 1);
+        assert_eq!(lignes[0]["source"], "TA/C.xml");
+        assert_eq!(lignes[0]["statut"], 400);
+        assert_eq!(lignes[0]["erreur"]["reason"], "date invalide");
+        assert_eq!(lignes[0]["action"]["index"]["_id"], "C");
+        assert_eq!(lignes[0]["document"]["id"], "C");
+    }
+
+    #[test]
+    fn place_en_lettre_morte_un_lot_sans_thread_d_envoi() {
+        let lettre_morte = chemin_temporaire("sans_thread.ndjson");
+        let mut client = ClientBulk::new(&ConfigurationElastic {
+            taille_lot_documents: 1,
+            ..configuration("http://127.0.0.1:9".to_string(), lettre_morte.clone())
+        });
+        let (emetteur, recepteur) = bounded::<Lot>(1);
+        drop(recepteur);
+        client.emetteur = emetteur;
+        for id in ["A", "B"] {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"{}"}}}}"#, id),
+                Some(&format!(r#"{{"id":"{}"}}"#, id)),
+                &format!("TA/{}.xml", id),
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.documents_envoyes, 2);
+        assert_eq!(bilan.documents_rejetes, 2);
+        let contenu = std::fs::read_to_string(&lettre_morte).unwrap();
+        std::fs::remove_file(&lettre_morte).unwrap();
+        assert_eq!(contenu.lines().count(), 2);
+    }
+
+    #[test]
+    fn compte_les_documents_que_la_lettre_morte_n_a_pas_pu_ecrire() {
+        let (url, _) = demarrer_serveur(vec![(400, "requête invalide".to_string())]);
+        let lettre_morte = chemin_temporaire("absent").join("rejets.ndjson");
+        let mut client = ClientBulk::new(&configuration(url, lettre_morte));
+        for id in ["A", "B"] {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"{}"}}}}"#, id),
+                Some(&format!(r#"{{"id":"{}"}}"#, id)),
+                "D.xml",
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.documents_rejetes, 2);
+        assert_eq!(bilan.lettre_morte_en_echec, 2);
+    }
+
+    #[test]
+    fn attend_l_acquittement_des_lots_avant_d_envoyer_la_suite() {
+        let (url, recues) = demarrer_serveur(Vec::new());
+        let mut client = ClientBulk::new(&ConfigurationElastic {
+            taille_lot_documents: 1,
+            requetes_simultanees: 4,
+            ..configuration(url, chemin_temporaire("attendre.ndjson"))
+        });
+        for i in 0..8 {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"D{}"}}}}"#, i),
+                Some(&format!(r#"{{"id":"D{}"}}"#, i)),
+                "D.xml",
+            );
+        }
+        client.attendre();
+        assert_eq!(recues.lock().unwrap().len(), 8);
+        client.ajouter(r#"{"delete":{"_id":"D9"}}"#, None, "D9.xml");
+        let bilan = client.terminer();
+        assert_eq!(bilan.lots_envoyes, 9);
+        assert_eq!(bilan.documents_indexes, 9);
+        let recues = recues.lock().unwrap();
+        assert_eq!(recues[8].corps, "{\"delete\":{\"_id\":\"D9\"}}\n");
+    }
+
+    #[test]
+    fn n_ignore_que_les_conflits_des_actions_versionnees() {
+        let reponse = json!({
+            "errors": true,
+            "items": [
+                {"index": {"_id": "A", "status": 409, "error": {"type": "version_conflict_engine_exception"}}},
+                {"create": {"_id": "B", "status": 409, "error": {"type": "version_conflict_engine_exception"}}}
+            ]
+        });
+        let (url, _) = demarrer_serveur(vec![(200, reponse.to_string())]);
+        let lettre_morte = chemin_temporaire("conflits.ndjson");
+        let mut client = ClientBulk::new(&configuration(url, lettre_morte.clone()));
+        client.ajouter(
+            r#"{"index":{"_id":"A","version":20230201,"version_type":"external_gte"}}"#,
+            Some(r#"{"id":"A"}"#),
+            "A.xml",
+        );
+        client.ajouter(r#"{"create":{"_id":"B"}}"#, Some(r#"{"id":"B"}"#), "B.xml");
+        let bilan = client.terminer();
+        assert_eq!(bilan.documents_ignores, 1);
+        assert_eq!(bilan.documents_rejetes, 1);
+        let contenu = std::fs::read_to_string(&lettre_morte).unwrap();
+        std::fs::remove_file(&lettre_morte).unwrap();
+        assert!(contenu.contains("B.xml") && !contenu.contains("A.xml"));
+    }
+
+    #[test]
+    fn coupe_en_deux_un_lot_trop_volumineux() {
+        let trop_gros = (413, "request entity too large".to_string());
+        // Lot de trois refusé, puis le premier document seul refusé.
+        let (url, recues) = demarrer_serveur(vec![trop_gros.clone(), trop_gros]);
+        let lettre_morte = chemin_temporaire("trop_volumineux.ndjson");
+        let mut client = ClientBulk::new(&configuration(url, lettre_morte.clone()));
+        for id in ["A", "B", "C"] {
+            client.ajouter(
+                &format!(r#"{{"index":{{"_id":"{}"}}}}"#, id),
+                Some(&format!(r#"{{"id":"{}"}}"#, id)),
+                &format!("{}.xml", id),
+            );
+        }
+        let bilan = client.terminer();
+        assert_eq!(bilan.documents_indexes, 2);
+        assert_eq!(bilan.documents_rejetes, 1);
+        let recues = recues.lock().unwrap();
+        let nombres: Vec<usize> = recues
+            .iter()
+            .map(|requete| requete.corps.lines().count() / 2)
+            .collect();
+        assert_eq!(nombres, [3, 1, 2]);
+        let contenu = std::fs::read_to_string(&lettre_morte).unwrap();
+        std::fs::remove_file(&lettre_morte).unwrap();
+        assert!(contenu.contains("A.xml") && contenu.contains("413"));
+        assert_eq!(contenu.lines().count(), 1);
+    }
+}
@@ -0,0 +1,415 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use xxhash_rust::xxh3::xxh3_64;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SignatureFichier {
+    pub taille: u64,
+    pub mtime_ns: u64,
+    pub empreinte: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EntreeEtat {
+    pub taille: u64,
+    pub mtime_ns: u64,
+    /// Empreinte xxh3 du contenu, en hexadécimal.
+    pub empreinte: String,
+    pub id: String,
+    pub index: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub routing: Option<String>,
+}
+
+/// Décision issue d'un fichier disparu ou remplacée sous un autre `_id` ou
+/// un autre index, à supprimer d'Elasticsearch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Suppression {
+    pub id: String,
+    pub index: String,
+    pub routing: Option<String>,
+    pub source: String,
+}
+
+pub fn empreinte(contenu: &[u8]) -> u64 {
+    xxh3_64(contenu)
+}
+
+/// Taille et date de modification, sans lire le contenu.
+pub fn taille_et_mtime(chemin: &Path) -> std::io::Result<(u64, u64)> {
+    let metadonnees = fs::metadata(chemin)?;
+    let mtime_ns = metadonnees
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    Ok((metadonnees.len(), mtime_ns))
+}
+
+/// État de l'ingestion incrémentale : l'état précédent, lu au démarrage, et
+/// l'état suivant, construit au fil du traitement et écrit en fin de run.
+///
+/// Un fichier de même taille et de même date de modification est considéré
+/// inchangé sans être lu ; sinon son empreinte est comparée à celle de
+/// l'état précédent avant de l'analyser.
+pub struct EtatIncremental {
+    precedent: BTreeMap<String, EntreeEtat>,
+    suivant: Mutex<BTreeMap<String, EntreeEtat>>,
+    presents: Mutex<HashSet<String>>,
+    /// Décisions précédentes dont le fichier produit désormais un autre `_id`
+    /// ou un autre index.
+    remplacees: Mutex<Vec<Suppression>>,
+}
+
+impl EtatIncremental {
+    /// Un fichier d'état absent correspond à un premier run.
+    pub fn charger(chemin: &Path) -> Result<Self, String> {
+        let precedent = if chemin.exists() {
+            let fichier = File::open(chemin)
+                .map_err(|e| format!("lecture de {} impossible: {}", chemin.display(), e))?;
+            serde_json::from_reader(BufReader::new(fichier))
+                .map_err(|e| format!("{}: {}", chemin.display(), e))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(EtatIncremental {
+            precedent,
+            suivant: Mutex::new(BTreeMap::new()),
+            presents: Mutex::new(HashSet::new()),
+            remplacees: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn nombre_precedent(&self) -> usize {
+        self.precedent.len()
+    }
+
+    /// Marque le fichier comme présent et reporte l'entrée précédente si la
+    /// taille et la date n'ont pas changé. Doit être appelé pour chaque
+    /// fichier trouvé, y compris dans les archives.
+    pub fn reporter_si_inchange(&self, cle: &str, taille: u64, mtime_ns: u64) -> bool {
+        self.presents.lock().unwrap().insert(cle.to_string());
+        match self.precedent.get(cle) {
+            Some(entree) if entree.taille == taille && entree.mtime_ns == mtime_ns => {
+                self.suivant
+                    .lock()
+                    .unwrap()
+                    .insert(cle.to_string(), entree.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reporte l'entrée précédente, avec la nouvelle signature, si seul le
+    /// contenu identique a été touché.
+    pub fn reporter_si_contenu_identique(&self, cle: &str, signature: SignatureFichier) -> bool {
+        match self.precedent.get(cle) {
+            Some(entree) if entree.empreinte == format!("{:016x}", signature.empreinte) => {
+                let mut entree = entree.clone();
+                entree.taille = signature.taille;
+                entree.mtime_ns = signature.mtime_ns;
+                self.suivant.lock().unwrap().insert(cle.to_string(), entree);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reporte l'entrée précédente telle quelle : le fichier n'a pas pu être
+    /// analysé et sera relu au prochain run.
+    pub fn conserver_precedent(&self, cle: &str) {
+        if let Some(entree) = self.precedent.get(cle) {
+            self.suivant
+                .lock()
+                .unwrap()
+                .insert(cle.to_string(), entree.clone());
+        }
+    }
+
+    /// Marque présentes toutes les entrées précédentes de l'archive illisible
+    /// et reporte celles qui n'ont pas été relues à ce run : aucune de ses
+    /// décisions n'est supprimée.
+    pub fn conserver_archive(&self, archive: &Path) {
+        let mut presents = self.presents.lock().unwrap();
+        let mut suivant = self.suivant.lock().unwrap();
+        for (cle, entree) in &self.precedent {
+            if Path::new(cle).starts_with(archive) {
+                presents.insert(cle.clone());
+                suivant.entry(cle.clone()).or_insert_with(|| entree.clone());
+            }
+        }
+    }
+
+    /// Enregistre la décision produite par le fichier. Si son `_id` ou son
+    /// index a changé, l'ancienne décision est retenue pour `suppressions`.
+    pub fn enregistrer(
+        &self,
+        cle: &str,
+        signature: SignatureFichier,
+        id: &str,
+        index: &str,
+        routing: Option<String>,
+    ) {
+        if let Some(ancienne) = self
+            .precedent
+            .get(cle)
+            .filter(|ancienne| ancienne.id != id || ancienne.index != index)
+        {
+            self.remplacees.lock().unwrap().push(Suppression {
+                id: ancienne.id.clone(),
+                index: ancienne.index.clone(),
+                routing: ancienne.routing.clone(),
+                source: cle.to_string(),
+            });
+        }
+        self.suivant.lock().unwrap().insert(
+            cle.to_string(),
+            EntreeEtat {
+                taille: signature.taille,
+                mtime_ns: signature.mtime_ns,
+                empreinte: format!("{:016x}", signature.empreinte),
+                id: id.to_string(),
+                index: index.to_string(),
+                routing,
+            },
+        );
+    }
+
+    /// Décisions à supprimer en fin de run : celles dont le fichier source n'a
+    /// pas été retrouvé et celles remplacées sous un autre `_id` ou un autre
+    /// index. Une décision dont le couple (`_id`, index) figure dans l'état
+    /// suivant est conservée : le fichier a été déplacé, renommé ou lu depuis
+    /// une archive, et la supprimer effacerait la version indexée à ce run.
+    pub fn suppressions(&self) -> Vec<Suppression> {
+        let mut conservees: HashSet<(String, String)> = self
+            .suivant
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entree| (entree.id.clone(), entree.index.clone()))
+            .collect();
+        let presents = self.presents.lock().unwrap();
+        let disparues = self
+            .precedent
+            .iter()
+            .filter(|(cle, _)| !presents.contains(*cle))
+            .map(|(cle, entree)| Suppression {
+                id: entree.id.clone(),
+                index: entree.index.clone(),
+                routing: entree.routing.clone(),
+                source: cle.clone(),
+            });
+        let remplacees = self.remplacees.lock().unwrap().clone();
+        // Une seule suppression par décision.
+        disparues
+            .chain(remplacees)
+            .filter(|suppression| {
+                conservees.insert((suppression.id.clone(), suppression.index.clone()))
+            })
+            .collect()
+    }
+
+    /// Écrit l'état suivant dans un fichier temporaire renommé ensuite, pour
+    /// ne jamais laisser un état tronqué.
+    pub fn sauvegarder(&self, chemin: &Path) -> std::io::Result<()> {
+        let temporaire = chemin.with_extension("tmp");
+        {
+            let mut fichier = BufWriter::new(File::create(&temporaire)?);
+            serde_json::to_writer(&mut fichier, &*self.suivant.lock().unwrap())?;
+            fichier.flush()?;
+        }
+        fs::rename(temporaire, chemin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(empreinte: u64) -> SignatureFichier {
+        SignatureFichier {
+            taille: 10,
+            mtime_ns: 100,
+            empreinte,
+        }
+    }
+
+    fn entree(id: &str, index: &str) -> EntreeEtat {
+        EntreeEtat {
+            taille: 10,
+            mtime_ns: 100,
+            empreinte: format!("{:016x}", 1),
+            id: id.to_string(),
+            index: index.to_string(),
+            routing: Some("TA".to_string()),
+        }
+    }
+
+    /// État précédent fait de (clé, `_id`, index).
+    fn etat(precedent: &[(&str, &str, &str)]) -> EtatIncremental {
+        EtatIncremental {
+            precedent: precedent
+                .iter()
+                .map(|(cle, id, index)| (cle.to_string(), entree(id, index)))
+                .collect(),
+            suivant: Mutex::new(BTreeMap::new()),
+            presents: Mutex::new(HashSet::new()),
+            remplacees: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn ids(suppressions: &[Suppression]) -> Vec<(&str, &str)> {
+        suppressions
+            .iter()
+            .map(|suppression| (suppression.id.as_str(), suppression.index.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reporte_un_fichier_de_meme_taille_et_date_sans_le_lire() {
+        let etat = etat(&[("a.xml", "DTA_1", "ta")]);
+        assert!(etat.reporter_si_inchange("a.xml", 10, 100));
+        assert!(!etat.reporter_si_inchange("b.xml", 10, 100));
+        let suivant = etat.suivant.lock().unwrap();
+        assert_eq!(suivant.keys().collect::<Vec<_>>(), ["a.xml"]);
+        assert_eq!(suivant["a.xml"].mtime_ns, 100);
+    }
+
+    #[test]
+    fn reporte_un_contenu_identique_avec_sa_nouvelle_signature() {
+        let etat = etat(&[("a.xml", "DTA_1", "ta")]);
+        assert!(!etat.reporter_si_inchange("a.xml", 10, 200));
+        let touche = SignatureFichier {
+            taille: 10,
+            mtime_ns: 200,
+            empreinte: 1,
+        };
+        assert!(etat.reporter_si_contenu_identique("a.xml", touche));
+        assert!(!etat.reporter_si_contenu_identique("a.xml", signature(2)));
+        assert_eq!(etat.suivant.lock().unwrap()["a.xml"].mtime_ns, 200);
+        assert!(etat.suppressions().is_empty());
+    }
+
+    #[test]
+    fn enregistre_une_decision_inchangee_modifiee_ou_deplacee() {
+        let etat = etat(&[
+            ("meme.xml", "DTA_1", "ta"),
+            ("id.xml", "DTA_2", "ta"),
+            ("index.xml", "DTA_3", "ta"),
+        ]);
+        for cle in ["meme.xml", "id.xml", "index.xml", "nouveau.xml"] {
+            etat.reporter_si_inchange(cle, 11, 200);
+        }
+        // Contenu modifié, même `_id` et même index : pas de suppression.
+        etat.enregistrer("meme.xml", signature(2), "DTA_1", "ta", None);
+        etat.enregistrer("id.xml", signature(3), "DTA_20", "ta", None);
+        etat.enregistrer("index.xml", signature(4), "DTA_3", "ta_2024", None);
+        etat.enregistrer("nouveau.xml", signature(5), "DTA_4", "ta", None);
+        let suppressions = etat.suppressions();
+        assert_eq!(ids(&suppressions), [("DTA_2", "ta"), ("DTA_3", "ta")]);
+        assert_eq!(suppressions[0].source, "id.xml");
+        assert_eq!(suppressions[0].routing.as_deref(), Some("TA"));
+        let suivant = etat.suivant.lock().unwrap();
+        assert_eq!(suivant["meme.xml"].empreinte, format!("{:016x}", 2));
+        assert_eq!(suivant["id.xml"].id, "DTA_20");
+        assert_eq!(suivant["index.xml"].index, "ta_2024");
+        assert_eq!(suivant.len(), 4);
+    }
+
+    #[test]
+    fn conserve_la_decision_d_un_fichier_illisible() {
+        let etat = etat(&[("a.xml", "DTA_1", "ta"), ("b.xml", "DTA_2", "ta")]);
+        etat.reporter_si_inchange("a.xml", 11, 200);
+        etat.conserver_precedent("a.xml");
+        etat.conserver_precedent("inconnu.xml");
+        assert_eq!(ids(&etat.suppressions()), [("DTA_2", "ta")]);
+        let suivant = etat.suivant.lock().unwrap();
+        assert_eq!(suivant.keys().collect::<Vec<_>>(), ["a.xml"]);
+        // Signature précédente : le fichier sera relu au prochain run.
+        assert_eq!(suivant["a.xml"].taille, 10);
+    }
+
+    #[test]
+    fn conserve_les_decisions_d_une_archive_illisible() {
+        let etat = etat(&[
+            ("TA.tar.gz/a.xml", "DTA_1", "ta"),
+            ("TA.tar.gz/b.xml", "DTA_2", "ta"),
+            ("TA.tar.gz.bak/c.xml", "DTA_3", "ta"),
+        ]);
+        // Entrée lue avant la corruption : sa nouvelle signature est gardée.
+        etat.reporter_si_inchange("TA.tar.gz/a.xml", 11, 200);
+        etat.enregistrer("TA.tar.gz/a.xml", signature(2), "DTA_1", "ta", None);
+        etat.conserver_archive(Path::new("TA.tar.gz"));
+        assert_eq!(ids(&etat.suppressions()), [("DTA_3", "ta")]);
+        let suivant = etat.suivant.lock().unwrap();
+        assert_eq!(suivant["TA.tar.gz/a.xml"].empreinte, format!("{:016x}", 2));
+        assert_eq!(suivant["TA.tar.gz/b.xml"].empreinte, format!("{:016x}", 1));
+        assert_eq!(suivant.len(), 2);
+    }
+
+    #[test]
+    fn supprime_les_decisions_des_fichiers_disparus() {
+        let etat = etat(&[
+            ("a.xml", "DTA_1", "ta"),
+            ("b.xml", "DTA_2", "ta"),
+            ("c.xml", "DTA_2", "ta"),
+        ]);
+        etat.reporter_si_inchange("a.xml", 10, 100);
+        // Deux fichiers disparus pour une même décision : une seule suppression.
+        assert_eq!(ids(&etat.suppressions()), [("DTA_2", "ta")]);
+    }
+
+    #[test]
+    fn sauvegarde_puis_recharge_l_etat_suivant() {
+        let chemin =
+            std::env::temp_dir().join(format!("xml_to_elastic_{}_etat.json", std::process::id()));
+        let _ = fs::remove_file(&chemin);
+        let premier = EtatIncremental::charger(&chemin).unwrap();
+        assert_eq!(premier.nombre_precedent(), 0);
+        premier.reporter_si_inchange("a.xml", 10, 100);
+        premier.enregistrer("a.xml", signature(1), "DTA_1", "ta", Some("TA".to_string()));
+        premier.sauvegarder(&chemin).unwrap();
+        assert!(!chemin.with_extension("tmp").exists());
+
+        let second = EtatIncremental::charger(&chemin).unwrap();
+        fs::remove_file(&chemin).unwrap();
+        assert_eq!(second.nombre_precedent(), 1);
+        assert!(second.reporter_si_inchange("a.xml", 10, 100));
+        let suivant = second.suivant.lock().unwrap();
+        assert_eq!(suivant["a.xml"].id, "DTA_1");
+        assert_eq!(suivant["a.xml"].routing.as_deref(), Some("TA"));
+        assert_eq!(suivant["a.xml"].empreinte, format!("{:016x}", 1));
+    }
+
+    #[test]
+    fn ne_supprime_pas_une_decision_reindexee_sous_une_autre_cle() {
+        // Le dossier extrait a été remplacé par l'archive : la clé change, la
+        // décision reste la même.
+        let etat = etat(&[
+            ("TA/DTA_1.xml", "DTA_1", "ta"),
+            ("TA/DTA_2.xml", "DTA_2", "ta"),
+        ]);
+        etat.reporter_si_inchange("TA.tar.gz/DTA_1.xml", 10, 200);
+        etat.enregistrer("TA.tar.gz/DTA_1.xml", signature(2), "DTA_1", "ta", None);
+        assert_eq!(ids(&etat.suppressions()), [("DTA_2", "ta")]);
+    }
+
+    #[test]
+    fn ne_supprime_pas_l_ancien_id_repris_par_un_autre_fichier() {
+        // Les deux fichiers ont échangé leurs identifiants.
+        let etat = etat(&[("a.xml", "DTA_1", "ta"), ("b.xml", "DTA_2", "ta")]);
+        for (cle, id) in [("a.xml", "DTA_2"), ("b.xml", "DTA_1")] {
+            etat.reporter_si_inchange(cle, 11, 200);
+            etat.enregistrer(cle, signature(2), id, "ta", None);
+        }
+        assert!(etat.suppressions().is_empty());
+    }
+}
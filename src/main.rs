@@ -1,39 +1,126 @@
-use crate::modele::{COMPTEURS_MANQUANTS, Decision, init_compteurs};
-use crate::parser::lire_decision_xml;
-use crate::texte::extraire_nom_index;
+use crate::action::ConstructeurAction;
+use crate::archive::{EntreeArchive, entrees_xml, est_archive};
+use crate::champs::{TableChamps, champs, installer_champs};
+use crate::configuration::{Commande, Configuration, ConfigurationElastic, parser_arguments};
+use crate::elastic::{AdministrationElastic, BilanEnvoi, ClientBulk};
+use crate::etat::{EtatIncremental, SignatureFichier, empreinte, taille_et_mtime};
+use crate::mapping::generer_modele_index;
+use crate::modele::{Decision, ENCODAGES, QUALITES, QualiteParsing, REPETITIONS};
+use crate::parser::{ErreurLecture, lire_decision, lire_decision_xml};
+use crate::profil::{CHAMPS_PROFIL_DEFAUT, Profil, cellule_csv, ecrire_profil};
+use crate::qualite::{RapportQualite, pourcentage};
+use crate::quarantaine::Quarantaine;
+use crate::rejets::RapportRejets;
+use crate::seuils::{BilanRun, verifier};
+use crate::solutions::{ReglesSolutions, installer_regles, non_reconnues, regles};
+use crate::texte::{formations_non_reconnues, types_recours_non_reconnus};
+
+use crossbeam_channel::Sender;
 
 use owo_colors::OwoColorize;
 use rayon::prelude::*;
 
 use std::env;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
-use std::sync::atomic::Ordering;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use walkdir::WalkDir;
 
+mod action;
+mod archive;
+mod champs;
+mod configuration;
+mod elastic;
+mod etat;
+mod mapping;
 mod modele;
 mod parser;
+mod profil;
+mod qualite;
+mod quarantaine;
+mod rejets;
+mod seuils;
+mod solutions;
 mod texte;
 
+/// Code de sortie d'un run dont le bilan dépasse les seuils ; les erreurs de
+/// configuration, d'écriture ou d'Elasticsearch sortent avec le code 1.
+const CODE_SEUILS_DEPASSES: i32 = 2;
+
+/// Résultat de la lecture d'un fichier, avec sa signature en mode incrémental.
+type Lue = Result<(Decision, Option<SignatureFichier>), ErreurLecture>;
+
 fn main() {
-    init_compteurs();
+    let programme = env::args().next().unwrap_or_default();
 
-    let arguments: Vec<String> = env::args().collect();
+    let mut configuration = match parser_arguments(env::args().skip(1)) {
+        Ok(configuration) => configuration,
+        Err(message) => {
+            eprintln!("{} {}", "Erreur:".red().bold(), message.red());
+            eprintln!(
+                "{} {} [--es URL] [--taille-lot N] [--taille-lot-octets N] [--requetes-simultanees N] [--gzip] [--config FICHIER.toml] [--champs FICHIER.toml] [--solutions FICHIER.toml] [--extra] [--recuperation] [--tentatives-max N] [--delai-initial-ms N] [--lettre-morte FICHIER] [--index MODELE] [--routing CHAMP|aucun] [--pipeline NOM] [--op-type index|create] [--version-externe date] [--installer-modele] [--alias NOM [--supprimer-ancien]] [--etat FICHIER] [--rejets FICHIER] [--qualite DOSSIER] [--quarantaine DOSSIER [--quarantaine-lien]] [--strict] [--max-rejets N] [--max-manquants CHAMP=POURCENTAGE] <dossier1> [dossier2 ... dossierN] [<output.jsonl>]",
+                "usage:".red().bold(),
+                programme.yellow()
+            );
+            eprintln!(
+                "{} {} profil [--config FICHIER.toml] [--champs FICHIER.toml] [--recuperation] [--champ CHAMP ...] [--format csv|json] [--exemples N] <dossier1> [dossier2 ... dossierN]",
+                "usage:".red().bold(),
+                programme.yellow()
+            );
+            eprintln!(
+                "{} {} solutions [--config FICHIER.toml] [--champs FICHIER.toml] [--solutions FICHIER.toml] [--recuperation] <dossier1> [dossier2 ... dossierN]",
+                "usage:".red().bold(),
+                programme.yellow()
+            );
+            eprintln!(
+                "{} {} mapping [--config FICHIER.toml] [--champs FICHIER.toml] [--extra] [--recuperation] [--index MODELE] [--nom-modele NOM] [--motif-index MOTIF] [--shards N] [--replicas N] [--es URL --installer-modele]",
+                "usage:".red().bold(),
+                programme.yellow()
+            );
+            std::process::exit(1);
+        }
+    };
 
-    if arguments.len() < 3 {
-        eprintln!(
-            "{} {} <dossier1> [dossier2 ... dossierN] <output.jsonl>",
-            "usage:".red().bold(),
-            arguments[0].yellow()
-        );
+    let table_champs = match &configuration.champs {
+        Some(chemin) => TableChamps::charger(chemin),
+        None => Ok(TableChamps::par_defaut()),
+    };
+
+    let installation = table_champs.and_then(|mut table| {
+        table.extra = configuration.extra;
+        installer_champs(table)
+    });
+
+    if let Err(message) = installation {
+        eprintln!("{} {}", "Erreur champs:".red().bold(), message.red());
         std::process::exit(1);
     }
 
-    let chemin_sortie = PathBuf::from(&arguments[arguments.len() - 1]);
+    if let Some(chemin) = &configuration.solutions
+        && let Err(message) = ReglesSolutions::charger(chemin).and_then(installer_regles)
+    {
+        eprintln!("{} {}", "Erreur solutions:".red().bold(), message.red());
+        std::process::exit(1);
+    }
 
-    if chemin_sortie.exists() && chemin_sortie.is_dir() {
+    for (champ, _) in &configuration.seuils.max_manquants {
+        if champ == "id" || !champs().noms().contains(champ) {
+            eprintln!(
+                "{} {}",
+                "Erreur:".red().bold(),
+                format!("--max-manquants: champ inconnu {}", champ).red()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(chemin_sortie) = &configuration.sortie
+        && chemin_sortie.exists()
+        && chemin_sortie.is_dir()
+    {
         eprintln!(
             "{} {}",
             "Erreur:".red().bold(),
@@ -42,7 +129,100 @@ fn main() {
         std::process::exit(1);
     }
 
-    let dossiers = &arguments[1..arguments.len() - 1];
+    let index_bleu_vert = configuration
+        .elastic
+        .as_ref()
+        .and_then(|elastic| elastic.alias.clone())
+        .filter(|_| configuration.commande == Commande::Ingestion)
+        .map(|alias| {
+            let index = format!("{}-{}", alias, chrono::Local::now().format("%Y%m%d%H%M%S"));
+
+            if configuration.modele.motifs.is_empty() {
+                configuration.modele.motifs.push(format!("{}-*", alias));
+            }
+
+            configuration.action.index = index.clone();
+
+            println!(
+                "{} {} {} {}",
+                "Index cible:".cyan().bold(),
+                index.bright_blue(),
+                "alias".bright_white(),
+                alias.bright_blue()
+            );
+
+            index
+        });
+
+    if configuration.commande == Commande::Mapping || configuration.modele.installer {
+        let modele = generer_modele_index(&configuration.modele, &configuration.action.index);
+
+        if configuration.commande == Commande::Mapping {
+            println!("{}", serde_json::to_string_pretty(&modele).unwrap());
+        }
+
+        if configuration.modele.installer
+            && let Some(elastic) = &configuration.elastic
+        {
+            match AdministrationElastic::new(&elastic.url)
+                .installer_modele_index(&configuration.modele.nom, &modele)
+            {
+                Ok(()) => eprintln!(
+                    "{} {}",
+                    "Modèle d'index installé:".green().bold(),
+                    configuration.modele.nom.bright_green()
+                ),
+                Err(message) => {
+                    eprintln!(
+                        "{} {}",
+                        "Erreur modèle d'index:".red().bold(),
+                        message.red()
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if configuration.commande == Commande::Mapping {
+            return;
+        }
+    }
+
+    let sous_commande = match configuration.commande {
+        Commande::Profil => Some(profiler(&configuration)),
+        Commande::Solutions => Some(tester_solutions(&configuration)),
+        _ => None,
+    };
+
+    if let Some(resultat) = sous_commande {
+        if let Err(message) = resultat {
+            eprintln!("{} {}", "Erreur:".red().bold(), message.red());
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    let constructeur_action = match ConstructeurAction::new(&configuration.action) {
+        Ok(constructeur) => constructeur,
+        Err(message) => {
+            eprintln!("{} {}", "Erreur:".red().bold(), message.red());
+            std::process::exit(1);
+        }
+    };
+
+    let etat = configuration
+        .etat
+        .as_ref()
+        .map(|chemin| match EtatIncremental::charger(chemin) {
+            Ok(etat) => Arc::new(etat),
+            Err(message) => {
+                eprintln!("{} {}", "Erreur état:".red().bold(), message.red());
+                std::process::exit(1);
+            }
+        });
+
+    let dossiers = &configuration.dossiers;
 
     println!(
         "{} {}",
@@ -50,60 +230,109 @@ fn main() {
         dossiers.len().to_string().yellow()
     );
 
-    let mut chemins_xml: Vec<PathBuf> = Vec::with_capacity(1_000_000);
-
     for dossier in dossiers {
         println!("{} {}", "Scan:".blue().bold(), dossier.bright_blue());
-
-        let iterateur = WalkDir::new(dossier)
-            .into_iter()
-            .filter_map(|entree| entree.ok())
-            .filter(|entree| {
-                entree.file_type().is_file()
-                    && entree
-                        .path()
-                        .extension()
-                        .map(|ext| ext == "xml")
-                        .unwrap_or(false)
-            })
-            .map(|entree| entree.into_path());
-
-        chemins_xml.extend(iterateur);
     }
 
+    let (mut chemins_xml, archives) = parcourir(dossiers);
+
     println!(
         "{} {}",
         "Fichiers XML trouvés:".green().bold(),
         chemins_xml.len().to_string().bright_green().bold()
     );
 
+    if !archives.is_empty() {
+        println!(
+            "{} {}",
+            "Archives trouvées:".green().bold(),
+            archives.len().to_string().bright_green().bold()
+        );
+    }
+
+    if let Some(etat) = &etat {
+        chemins_xml.retain(|chemin| match taille_et_mtime(chemin) {
+            Ok((taille, mtime_ns)) => {
+                !etat.reporter_si_inchange(&chemin.to_string_lossy(), taille, mtime_ns)
+            }
+            Err(_) => true,
+        });
+
+        println!(
+            "{} {} {} {} {}",
+            "Incrémental:".cyan().bold(),
+            "connus".bright_white(),
+            etat.nombre_precedent().to_string().bright_blue(),
+            "fichiers à examiner".bright_white(),
+            chemins_xml.len().to_string().bright_green()
+        );
+    }
+
     use crossbeam_channel::unbounded;
 
-    let (tx, rx) = unbounded::<Decision>();
+    let recuperation = configuration.recuperation;
+
+    let quarantaine = configuration
+        .quarantaine
+        .as_ref()
+        .map(|dossier| Quarantaine::new(dossier, configuration.quarantaine_lien));
+
+    let rejets = RapportRejets::new(&configuration.rejets, quarantaine);
+
+    let mut fichier = match &configuration.sortie {
+        Some(chemin) => match File::create(chemin) {
+            Ok(fichier) => Some(BufWriter::new(fichier)),
+            Err(e) => {
+                eprintln!(
+                    "{} {} {}",
+                    "Erreur écriture:".red().bold(),
+                    chemin.display(),
+                    e.to_string().red()
+                );
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let (tx, rx) = unbounded::<(Decision, Option<SignatureFichier>)>();
 
     let thread_ecriture = std::thread::spawn({
-        let chemin_sortie = chemin_sortie.clone();
+        let chemin_sortie = configuration.sortie.clone().unwrap_or_default();
+        let configuration_elastic = configuration.elastic.clone();
+        let etat = etat.clone();
+
+        move || -> Result<(usize, usize, Option<BilanEnvoi>), String> {
+            let mut client = configuration_elastic.as_ref().map(ClientBulk::new);
 
-        move || {
-            let mut fichier = File::create(chemin_sortie).unwrap();
+            let erreur_ecriture = |e: std::io::Error| {
+                format!("écriture de {} impossible: {}", chemin_sortie.display(), e)
+            };
 
             let mut total_ecrit = 0usize;
+            let mut total_supprime = 0usize;
 
-            for decision in rx {
-                let nom_index = extraire_nom_index(&*decision.id);
+            for (decision, signature) in rx {
+                let valeur = serde_json::to_value(&decision).map_err(|e| e.to_string())?;
 
-                let meta = format!(
-                    r#"{{"index":{{"_index":"{}","_id":"{}"}}}}"#,
-                    nom_index, decision.id
-                );
+                let source = decision.chemin_source.to_string_lossy();
+
+                if let (Some(etat), Some(signature)) = (&etat, signature) {
+                    let (index, routing) = constructeur_action.destination(&valeur);
 
-                writeln!(fichier, "{}", meta).unwrap();
+                    etat.enregistrer(&source, signature, &decision.id, &index, routing);
+                }
 
-                writeln!(fichier, "{}", serde_json::to_string(&decision).unwrap()).unwrap();
+                let meta = constructeur_action.construire(&valeur);
+
+                let document = valeur.to_string();
+
+                emettre(&mut fichier, &mut client, &meta, Some(&document), &source)
+                    .map_err(erreur_ecriture)?;
 
                 total_ecrit += 1;
 
-                if total_ecrit % 100_000 == 0 {
+                if total_ecrit.is_multiple_of(100_000) {
                     println!(
                         "{} {}",
                         "Progression:".magenta().bold(),
@@ -112,23 +341,196 @@ fn main() {
                 }
             }
 
-            total_ecrit
+            if let Some(etat) = &etat {
+                let suppressions = etat.suppressions();
+
+                // Une suppression ne doit jamais arriver avant, ni être
+                // renvoyée après, l'indexation d'un même `_id` : elle ne part
+                // qu'une fois tous les lots d'indexation acquittés.
+                if !suppressions.is_empty()
+                    && let Some(client) = client.as_mut()
+                {
+                    client.attendre();
+                }
+
+                for suppression in suppressions {
+                    let meta = constructeur_action.construire_suppression(
+                        &suppression.index,
+                        &suppression.id,
+                        suppression.routing.as_deref(),
+                    );
+
+                    emettre(&mut fichier, &mut client, &meta, None, &suppression.source)
+                        .map_err(erreur_ecriture)?;
+
+                    total_supprime += 1;
+                }
+            }
+
+            if let Some(fichier) = fichier.as_mut() {
+                fichier.flush().map_err(erreur_ecriture)?;
+            }
+
+            Ok((
+                total_ecrit,
+                total_supprime,
+                client.map(ClientBulk::terminer),
+            ))
         }
     });
 
     println!("{}", "Traitement parallèle en cours...".cyan().bold());
 
-    chemins_xml
-        .into_par_iter()
-        .for_each_with(tx.clone(), |tx, chemin| {
-            if let Some(decision) = lire_decision_xml(&chemin) {
-                let _ = tx.send(decision);
-            }
-        });
+    let mut ecriture_rejets =
+        chemins_xml
+            .into_par_iter()
+            .try_for_each_with(tx.clone(), |tx, chemin| {
+                let lue = match &etat {
+                    Some(etat) => match taille_et_mtime(&chemin)
+                        .and_then(|(_, mtime_ns)| Ok((mtime_ns, std::fs::read(&chemin)?)))
+                    {
+                        Ok((mtime_ns, contenu)) => {
+                            lire_si_modifie(etat, &chemin, mtime_ns, &contenu, recuperation)
+                        }
+                        Err(e) => Some(Err(ErreurLecture::Io(e.to_string()))),
+                    },
+                    None => Some(
+                        lire_decision_xml(&chemin, recuperation).map(|decision| (decision, None)),
+                    ),
+                };
+
+                transmettre(tx, &rejets, etat.as_deref(), &chemin, None, lue)
+            });
+
+    let entrees_archives = AtomicUsize::new(0);
+
+    for archive in &archives {
+        if ecriture_rejets.is_err() {
+            break;
+        }
+
+        println!(
+            "{} {}",
+            "Archive:".blue().bold(),
+            archive.display().bright_blue()
+        );
+
+        let nom_archive = archive.to_string_lossy().to_string();
+
+        ecriture_rejets = entrees_xml(archive.clone())
+            .into_iter()
+            .par_bridge()
+            .try_for_each_with(tx.clone(), |tx, entree| {
+                let entree = match entree {
+                    Ok(entree) => entree,
+                    Err(e) => {
+                        rejets.enregistrer(
+                            archive,
+                            None,
+                            &ErreurLecture::ArchiveIllisible(e.to_string()),
+                        )?;
+
+                        // Les entrées non lues ne doivent pas être supprimées
+                        // de l'index.
+                        if let Some(etat) = &etat {
+                            etat.conserver_archive(archive);
+                        }
+
+                        return Ok(());
+                    }
+                };
+
+                entrees_archives.fetch_add(1, Ordering::Relaxed);
+
+                let source = archive.join(&entree.chemin);
+
+                let lue = match &etat {
+                    Some(etat) => {
+                        let inchange = etat.reporter_si_inchange(
+                            &source.to_string_lossy(),
+                            entree.contenu.len() as u64,
+                            entree.mtime_ns,
+                        );
+
+                        if inchange {
+                            None
+                        } else {
+                            lire_si_modifie(
+                                etat,
+                                &source,
+                                entree.mtime_ns,
+                                &entree.contenu,
+                                recuperation,
+                            )
+                        }
+                    }
+                    None => Some(
+                        lire_decision(&entree.contenu[..], &source, recuperation)
+                            .map(|decision| (decision, None)),
+                    ),
+                };
+
+                let lue = lue.map(|lue| {
+                    lue.map(|(decision, signature)| {
+                        (
+                            situer_dans_archive(decision, &nom_archive, &entree),
+                            signature,
+                        )
+                    })
+                });
+
+                transmettre(
+                    tx,
+                    &rejets,
+                    etat.as_deref(),
+                    &source,
+                    Some(&entree.contenu),
+                    lue,
+                )
+            });
+    }
+
+    if !archives.is_empty() {
+        println!(
+            "{} {}",
+            "Entrées XML lues dans les archives:".green().bold(),
+            entrees_archives
+                .load(Ordering::Relaxed)
+                .to_string()
+                .bright_green()
+                .bold()
+        );
+    }
 
     drop(tx);
 
-    let total = thread_ecriture.join().unwrap();
+    let ecriture_rejets = ecriture_rejets.and_then(|()| rejets.terminer());
+
+    let (total, total_supprime, bilan_envoi) = match thread_ecriture.join() {
+        Ok(Ok(bilan)) => bilan,
+        Ok(Err(message)) => {
+            eprintln!("{} {}", "Erreur écriture:".red().bold(), message.red());
+            std::process::exit(1);
+        }
+        Err(_) => {
+            eprintln!(
+                "{} {}",
+                "Erreur écriture:".red().bold(),
+                "le thread d'écriture s'est arrêté".red()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = ecriture_rejets {
+        eprintln!(
+            "{} {} {}",
+            "Erreur rapport des rejets:".red().bold(),
+            rejets.chemin().display(),
+            e.to_string().red()
+        );
+        std::process::exit(1);
+    }
 
     println!(
         "{} {}",
@@ -136,21 +538,185 @@ fn main() {
         total.to_string().bright_green().bold()
     );
 
-    println!("\n{}", "Statistiques des champs manquants".yellow().bold());
+    if rejets.total() > 0 {
+        println!(
+            "{} {} {} {}",
+            "Fichiers rejetés:".red().bold(),
+            rejets.total().to_string().red(),
+            "voir".bright_white(),
+            rejets.chemin().display().bright_yellow()
+        );
 
-    let mut stats: Vec<(&str, usize)> = COMPTEURS_MANQUANTS
-        .iter()
-        .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
-        .collect();
+        for (categorie, nombre) in rejets.compteurs() {
+            println!(
+                "  {} {}",
+                categorie.bright_white(),
+                nombre.to_string().red()
+            );
+        }
 
-    stats.sort_by(|a, b| b.1.cmp(&a.1));
+        if let Some(quarantaine) = rejets.quarantaine() {
+            println!(
+                "{} {} {} {}",
+                "Quarantaine:".yellow().bold(),
+                quarantaine.nombre().to_string().yellow(),
+                "fichiers dans".bright_white(),
+                quarantaine.dossier().display().bright_yellow()
+            );
+        }
+    }
 
-    for (champ, nombre) in stats {
-        let pourcentage = if total > 0 {
-            (nombre as f64 / total as f64) * 100.0
-        } else {
-            0.0
-        };
+    if total_supprime > 0 {
+        println!(
+            "{} {}",
+            "Suppressions:".yellow().bold(),
+            total_supprime.to_string().yellow()
+        );
+    }
+
+    if let Some(bilan) = &bilan_envoi {
+        println!(
+            "{} {} {} {} {} {} {}",
+            "Elasticsearch:".cyan().bold(),
+            "documents indexés".bright_white(),
+            bilan.documents_indexes.to_string().bright_green(),
+            "sur".bright_white(),
+            bilan.documents_envoyes.to_string().bright_green(),
+            "lots".bright_white(),
+            bilan.lots_envoyes.to_string().bright_blue()
+        );
+
+        if bilan.documents_reessayes > 0 {
+            println!(
+                "{} {} {}",
+                "Elasticsearch:".yellow().bold(),
+                "renvois".bright_white(),
+                bilan.documents_reessayes.to_string().yellow()
+            );
+        }
+
+        if bilan.documents_ignores > 0 {
+            println!(
+                "{} {} {}",
+                "Elasticsearch:".yellow().bold(),
+                "conflits de version ignorés".bright_white(),
+                bilan.documents_ignores.to_string().yellow()
+            );
+        }
+
+        if bilan.documents_rejetes > 0 {
+            println!(
+                "{} {} {} {} {}",
+                "Elasticsearch:".red().bold(),
+                "documents rejetés".bright_white(),
+                bilan.documents_rejetes.to_string().red(),
+                "voir".bright_white(),
+                configuration
+                    .elastic
+                    .as_ref()
+                    .map(|c| c.lettre_morte.display().to_string())
+                    .unwrap_or_default()
+                    .bright_yellow()
+            );
+        }
+    }
+
+    if let Some(bilan) = &bilan_envoi
+        && bilan.lettre_morte_en_echec > 0
+    {
+        eprintln!(
+            "{} {} {}",
+            "Erreur lettre morte:".red().bold(),
+            bilan.lettre_morte_en_echec.to_string().red(),
+            "documents rejetés n'ont pas pu être consignés".red()
+        );
+        std::process::exit(1);
+    }
+
+    let rapport_qualite = RapportQualite::construire();
+
+    let chemin_qualite = match rapport_qualite.ecrire(&configuration.qualite) {
+        Ok(chemin) => chemin,
+        Err(e) => {
+            eprintln!(
+                "{} {} {}",
+                "Erreur rapport qualité:".red().bold(),
+                configuration.qualite.display(),
+                e.to_string().red()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let absents = rapport_qualite.absents();
+
+    let depassements = verifier(
+        &configuration.seuils,
+        &BilanRun {
+            decisions: rapport_qualite.decisions,
+            fichiers_rejetes: rejets.total(),
+            documents_refuses: bilan_envoi
+                .as_ref()
+                .map(|bilan| bilan.documents_rejetes)
+                .unwrap_or(0),
+            manquants: &absents,
+        },
+    );
+
+    if index_bleu_vert.is_some() && !depassements.is_empty() {
+        eprintln!(
+            "{} {}",
+            "Alias non basculé:".yellow().bold(),
+            "seuils dépassés".yellow()
+        );
+    }
+
+    if let (Some(index), Some(elastic), Some(bilan)) =
+        (&index_bleu_vert, &configuration.elastic, &bilan_envoi)
+        && depassements.is_empty()
+        && let Err(message) = basculer_alias(elastic, index, bilan.documents_envoyes)
+    {
+        eprintln!(
+            "{} {}",
+            "Erreur bascule d'alias:".red().bold(),
+            message.red()
+        );
+        std::process::exit(1);
+    }
+
+    if let (Some(etat), Some(chemin)) = (&etat, &configuration.etat) {
+        let rejets = bilan_envoi
+            .as_ref()
+            .map(|bilan| bilan.documents_rejetes)
+            .unwrap_or(0);
+
+        if rejets > 0 {
+            eprintln!(
+                "{} {}",
+                "État non mis à jour:".yellow().bold(),
+                "des documents ont été rejetés, ils seront renvoyés au prochain run".yellow()
+            );
+        } else if !depassements.is_empty() {
+            eprintln!(
+                "{} {}",
+                "État non mis à jour:".yellow().bold(),
+                "seuils dépassés, les fichiers seront relus au prochain run".yellow()
+            );
+        } else if let Err(e) = etat.sauvegarder(chemin) {
+            eprintln!(
+                "{} {} {}",
+                "Erreur état:".red().bold(),
+                chemin.display(),
+                e.to_string().red()
+            );
+            std::process::exit(1);
+        }
+    }
+
+    println!("\n{}", "Qualité des champs".yellow().bold());
+
+    for bilan in &rapport_qualite.champs {
+        let pourcentage = pourcentage(bilan.absents(), rapport_qualite.decisions);
 
         let couleur = if pourcentage > 50.0 {
             format!("{:.1}%", pourcentage).red().to_string()
@@ -160,13 +726,538 @@ fn main() {
             format!("{:.1}%", pourcentage).green().to_string()
         };
 
+        let mut details = format!("{} manquants, {} vides", bilan.manquants, bilan.vides);
+
+        if bilan.dates_invalides > 0 {
+            details.push_str(&format!(", {} dates invalides", bilan.dates_invalides));
+        }
+
         println!(
-            "{} {} {}",
-            champ.bright_white().bold(),
-            nombre.to_string().bright_blue(),
-            couleur
+            "{} {} {} {}",
+            bilan.champ.bright_white().bold(),
+            bilan.presents.to_string().bright_blue(),
+            couleur,
+            details.bright_black()
+        );
+    }
+
+    println!(
+        "{} {}",
+        "Rapport qualité:".bright_white(),
+        chemin_qualite.display().bright_yellow()
+    );
+
+    println!("\n{}", "Encodages".yellow().bold());
+
+    let mut encodages: Vec<(String, usize)> = ENCODAGES
+        .iter()
+        .map(|e| (e.key().clone(), e.value().load(Ordering::Relaxed)))
+        .collect();
+
+    encodages.sort_by_key(|e| std::cmp::Reverse(e.1));
+
+    for (encodage, nombre) in encodages {
+        println!(
+            "{} {}",
+            encodage.bright_white().bold(),
+            nombre.to_string().bright_blue()
+        );
+    }
+
+    if !QUALITES.is_empty() {
+        println!("\n{}", "Qualité du parsing".yellow().bold());
+
+        let mut qualites: Vec<(&str, usize)> = QUALITES
+            .iter()
+            .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
+            .collect();
+
+        qualites.sort_by_key(|q| std::cmp::Reverse(q.1));
+
+        for (qualite, nombre) in qualites {
+            let nombre = if qualite == QualiteParsing::Complete.libelle() {
+                nombre.to_string().green().to_string()
+            } else {
+                nombre.to_string().red().to_string()
+            };
+
+            println!("{} {}", qualite.bright_white().bold(), nombre);
+        }
+    }
+
+    if !REPETITIONS.is_empty() {
+        println!("\n{}", "Champs répétés".yellow().bold());
+
+        let mut repetitions: Vec<(String, usize, usize)> = REPETITIONS
+            .iter()
+            .map(|e| (e.key().clone(), e.decisions, e.occurrences_max))
+            .collect();
+
+        repetitions.sort_by_key(|r| std::cmp::Reverse(r.1));
+
+        let table = champs();
+
+        for (champ, decisions, occurrences_max) in repetitions {
+            let multiple = table
+                .definitions
+                .iter()
+                .any(|definition| definition.nom == champ && definition.multiple);
+
+            let conservation = if multiple {
+                "liste".green().to_string()
+            } else {
+                "dernière valeur conservée".red().to_string()
+            };
+
+            println!(
+                "{} {} {} {} {} {}",
+                champ.bright_white().bold(),
+                decisions.to_string().bright_blue(),
+                "décisions".bright_white(),
+                "jusqu'à".bright_white(),
+                occurrences_max.to_string().bright_blue(),
+                conservation
+            );
+        }
+    }
+
+    let solutions_non_reconnues = non_reconnues();
+
+    if !solutions_non_reconnues.is_empty() {
+        println!(
+            "\n{} {} {} {}",
+            "Solutions non reconnues".yellow().bold(),
+            solutions_non_reconnues.len().to_string().yellow(),
+            "règles version".bright_white(),
+            regles().version.bright_blue()
+        );
+
+        for (solution, occurrences) in solutions_non_reconnues.iter().take(10) {
+            println!(
+                "{} {}",
+                solution.bright_white().bold(),
+                occurrences.to_string().bright_blue()
+            );
+        }
+
+        if solutions_non_reconnues.len() > 10 {
+            println!(
+                "{}",
+                "liste complète: sous-commande solutions".bright_black()
+            );
+        }
+    }
+
+    let types_recours_non_reconnus = types_recours_non_reconnus();
+
+    if !types_recours_non_reconnus.is_empty() {
+        println!(
+            "\n{} {}",
+            "Types de recours non reconnus".yellow().bold(),
+            types_recours_non_reconnus.len().to_string().yellow()
         );
+
+        for (type_recours, occurrences) in &types_recours_non_reconnus {
+            println!(
+                "{} {}",
+                type_recours.bright_white().bold(),
+                occurrences.to_string().bright_blue()
+            );
+        }
+    }
+
+    let formations_non_reconnues = formations_non_reconnues();
+
+    if !formations_non_reconnues.is_empty() {
+        println!(
+            "\n{} {}",
+            "Formations de jugement non reconnues".yellow().bold(),
+            formations_non_reconnues.len().to_string().yellow()
+        );
+
+        for (formation, occurrences) in formations_non_reconnues.iter().take(10) {
+            println!(
+                "{} {}",
+                formation.bright_white().bold(),
+                occurrences.to_string().bright_blue()
+            );
+        }
+
+        if formations_non_reconnues.len() > 10 {
+            println!(
+                "{}",
+                "liste complète: sous-commande profil --champ formation_jugement".bright_black()
+            );
+        }
+    }
+
+    if !depassements.is_empty() {
+        println!("\n{}", "Seuils dépassés".red().bold());
+
+        for depassement in &depassements {
+            println!("  {}", depassement.red());
+        }
+
+        eprintln!("{}", "Échec.".red().bold());
+        std::process::exit(CODE_SEUILS_DEPASSES);
     }
 
     println!("{}", "Terminé.".bright_green().bold());
 }
+
+fn emettre(
+    fichier: &mut Option<BufWriter<File>>,
+    client: &mut Option<ClientBulk>,
+    action: &str,
+    document: Option<&str>,
+    source: &str,
+) -> std::io::Result<()> {
+    if let Some(fichier) = fichier.as_mut() {
+        writeln!(fichier, "{}", action)?;
+
+        if let Some(document) = document {
+            writeln!(fichier, "{}", document)?;
+        }
+    }
+
+    if let Some(client) = client.as_mut() {
+        client.ajouter(action, document, source);
+    }
+
+    Ok(())
+}
+
+/// Renseigne l'archive et le chemin de l'entrée d'où provient la décision.
+fn situer_dans_archive(mut decision: Decision, archive: &str, entree: &EntreeArchive) -> Decision {
+    decision.archive = Some(archive.to_string());
+    decision.entree_archive = Some(entree.chemin.clone());
+
+    decision
+}
+
+/// Transmet la décision lue au thread d'écriture, ou consigne le rejet du
+/// fichier. `None` : fichier inchangé depuis l'état précédent.
+fn transmettre(
+    tx: &Sender<(Decision, Option<SignatureFichier>)>,
+    rejets: &RapportRejets,
+    etat: Option<&EtatIncremental>,
+    source: &Path,
+    contenu: Option<&[u8]>,
+    lue: Option<Lue>,
+) -> std::io::Result<()> {
+    match lue {
+        Some(Ok(lue)) => {
+            let _ = tx.send(lue);
+        }
+        Some(Err(erreur)) => {
+            rejets.enregistrer(source, contenu, &erreur)?;
+
+            // La décision indexée auparavant reste en place jusqu'à ce que le
+            // fichier soit corrigé.
+            if let Some(etat) = etat {
+                etat.conserver_precedent(&source.to_string_lossy());
+            }
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Fichiers XML et archives sous les dossiers donnés.
+fn parcourir(dossiers: &[String]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut chemins_xml: Vec<PathBuf> = Vec::with_capacity(1_000_000);
+
+    let mut archives: Vec<PathBuf> = Vec::new();
+
+    for dossier in dossiers {
+        let iterateur = WalkDir::new(dossier)
+            .into_iter()
+            .filter_map(|entree| entree.ok())
+            .filter(|entree| entree.file_type().is_file())
+            .map(|entree| entree.into_path());
+
+        for chemin in iterateur {
+            if chemin.extension().map(|ext| ext == "xml").unwrap_or(false) {
+                chemins_xml.push(chemin);
+            } else if est_archive(&chemin) {
+                archives.push(chemin);
+            }
+        }
+    }
+
+    (chemins_xml, archives)
+}
+
+/// Analyse tous les fichiers des dossiers sans rien produire d'autre que les
+/// décisions passées à `retenir` ; la progression va sur la sortie d'erreur.
+fn analyser(configuration: &Configuration, titre: &str, retenir: impl Fn(Decision) + Sync) {
+    let (chemins_xml, archives) = parcourir(&configuration.dossiers);
+
+    eprintln!(
+        "{} {} {} {}",
+        titre.cyan().bold(),
+        chemins_xml.len().to_string().bright_green(),
+        "fichiers XML,".bright_white(),
+        format!("{} archives", archives.len()).bright_white()
+    );
+
+    let rejetes = AtomicUsize::new(0);
+    let recuperation = configuration.recuperation;
+
+    let traiter = |lue: Result<Decision, ErreurLecture>| match lue {
+        Ok(decision) => retenir(decision),
+        Err(_) => {
+            rejetes.fetch_add(1, Ordering::Relaxed);
+        }
+    };
+
+    chemins_xml
+        .par_iter()
+        .for_each(|chemin| traiter(lire_decision_xml(chemin, recuperation)));
+
+    for archive in &archives {
+        entrees_xml(archive.clone())
+            .into_iter()
+            .par_bridge()
+            .for_each(|entree| match entree {
+                Ok(entree) => {
+                    let source = archive.join(&entree.chemin);
+                    traiter(lire_decision(&entree.contenu[..], &source, recuperation));
+                }
+                Err(e) => traiter(Err(ErreurLecture::ArchiveIllisible(e.to_string()))),
+            });
+    }
+
+    let rejetes = rejetes.into_inner();
+
+    if rejetes > 0 {
+        eprintln!(
+            "{} {}",
+            "Fichiers rejetés:".red().bold(),
+            rejetes.to_string().red()
+        );
+    }
+}
+
+/// Sous-commande `solutions` : les solutions non reconnues en CSV sur la
+/// sortie standard, le nombre de correspondances de chaque règle sur la sortie
+/// d'erreur.
+fn tester_solutions(configuration: &Configuration) -> Result<(), String> {
+    analyser(configuration, "Solutions:", |_| {});
+
+    let regles = regles();
+
+    eprintln!(
+        "\n{} {} {}",
+        "Règles".yellow().bold(),
+        "version".bright_white(),
+        regles.version.bright_blue()
+    );
+
+    for regle in &regles.regles {
+        let correspondances = regle.correspondances();
+
+        let nombre = if correspondances == 0 {
+            "0".red().to_string()
+        } else {
+            correspondances.to_string().bright_blue().to_string()
+        };
+
+        eprintln!(
+            "{} {} {:?} {}",
+            regle.libelle.bright_white().bold(),
+            regle.priorite.to_string().bright_black(),
+            regle.motif,
+            nombre
+        );
+    }
+
+    let non_reconnues = non_reconnues();
+
+    eprintln!(
+        "{} {}",
+        "Solutions non reconnues:".yellow().bold(),
+        non_reconnues.len().to_string().yellow()
+    );
+
+    let sortie = std::io::stdout();
+    let mut sortie = BufWriter::new(sortie.lock());
+
+    writeln!(sortie, "solution,occurrences")
+        .and_then(|()| {
+            for (solution, occurrences) in &non_reconnues {
+                writeln!(sortie, "{},{}", cellule_csv(solution), occurrences)?;
+            }
+
+            sortie.flush()
+        })
+        .map_err(|e| format!("écriture des solutions non reconnues: {}", e))
+}
+
+/// Sous-commande `profil` : les valeurs des champs sur la sortie standard, la
+/// progression sur la sortie d'erreur.
+fn profiler(configuration: &Configuration) -> Result<(), String> {
+    let table = champs();
+
+    let noms: Vec<String> = if configuration.profil.champs.is_empty() {
+        CHAMPS_PROFIL_DEFAUT
+            .iter()
+            .filter(|nom| table.noms().iter().any(|n| n == *nom))
+            .map(|nom| nom.to_string())
+            .collect()
+    } else {
+        for nom in &configuration.profil.champs {
+            if nom == "id" || !table.noms().contains(nom) {
+                return Err(format!("--champ: champ inconnu {}", nom));
+            }
+        }
+
+        configuration.profil.champs.clone()
+    };
+
+    let profil = Profil::new(noms, configuration.profil.exemples);
+
+    analyser(configuration, "Profil:", |decision| {
+        profil.ajouter(&decision)
+    });
+
+    let sortie = std::io::stdout();
+    let mut sortie = BufWriter::new(sortie.lock());
+
+    ecrire_profil(&profil.lignes(), configuration.profil.format, &mut sortie)
+        .and_then(|()| sortie.flush())
+        .map_err(|e| format!("écriture du profil: {}", e))
+}
+
+/// N'analyse le contenu que s'il diffère de celui enregistré dans l'état
+/// précédent.
+fn lire_si_modifie(
+    etat: &EtatIncremental,
+    source: &Path,
+    mtime_ns: u64,
+    contenu: &[u8],
+    recuperation: bool,
+) -> Option<Lue> {
+    let signature = SignatureFichier {
+        taille: contenu.len() as u64,
+        mtime_ns,
+        empreinte: empreinte(contenu),
+    };
+
+    if etat.reporter_si_contenu_identique(&source.to_string_lossy(), signature) {
+        return None;
+    }
+
+    Some(lire_decision(contenu, source, recuperation).map(|decision| (decision, Some(signature))))
+}
+
+/// Vérifie que le nouvel index contient toutes les décisions envoyées avant
+/// d'y basculer l'alias, puis supprime éventuellement les anciens index. Un
+/// run qui n'a rien envoyé ne bascule jamais l'alias vers un index vide.
+fn basculer_alias(
+    elastic: &ConfigurationElastic,
+    index: &str,
+    attendus: usize,
+) -> Result<(), String> {
+    let alias = elastic.alias.as_deref().unwrap_or_default();
+
+    if attendus == 0 {
+        return Err(format!(
+            "aucun document envoyé dans {}, l'alias {} n'est pas modifié",
+            index, alias
+        ));
+    }
+
+    let administration = AdministrationElastic::new(&elastic.url);
+
+    let compte = administration.compter(index)?;
+
+    if compte != attendus {
+        return Err(format!(
+            "{} contient {} documents sur {} envoyés, l'alias {} n'est pas modifié",
+            index, compte, attendus, alias
+        ));
+    }
+
+    let anciens: Vec<String> = administration
+        .indices_alias(alias)?
+        .into_iter()
+        .filter(|ancien| ancien != index)
+        .collect();
+
+    administration.basculer_alias(alias, index, &anciens)?;
+
+    println!(
+        "{} {} {} {}",
+        "Alias basculé:".green().bold(),
+        alias.bright_green(),
+        "→".bright_white(),
+        index.bright_green()
+    );
+
+    if elastic.supprimer_ancien {
+        for ancien in &anciens {
+            administration.supprimer_index(ancien)?;
+
+            println!("{} {}", "Index supprimé:".yellow().bold(), ancien.yellow());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ne_bascule_pas_l_alias_sans_document_envoye() {
+        // Aucune requête n'est faite : l'adresse ne répond pas.
+        let elastic = ConfigurationElastic {
+            url: "http://127.0.0.1:9".to_string(),
+            taille_lot_documents: 1,
+            taille_lot_octets: 1,
+            requetes_simultanees: 1,
+            gzip: false,
+            tentatives_max: 1,
+            delai_initial_ms: 1,
+            lettre_morte: PathBuf::from("lettre_morte.ndjson"),
+            alias: Some("decisions".to_string()),
+            supprimer_ancien: true,
+        };
+        let erreur = basculer_alias(&elastic, "decisions-20240101000000", 0).unwrap_err();
+        assert!(erreur.contains("aucun document envoyé"), "{}", erreur);
+    }
+
+    #[test]
+    fn situe_la_decision_dans_son_archive() {
+        let xml = b"<Document><Donnees_Techniques><Identification>DTA_2300001</Identification></Donnees_Techniques></Document>";
+        let mut entete = tar::Header::new_gnu();
+        entete.set_size(xml.len() as u64);
+        entete.set_mode(0o644);
+        entete.set_cksum();
+        let mut constructeur = tar::Builder::new(Vec::new());
+        constructeur
+            .append_data(&mut entete, "JADE/2023/DTA_2300001.xml", &xml[..])
+            .unwrap();
+        let archive = std::env::temp_dir().join(format!(
+            "xml_to_elastic_{}_JADE_20240101.tar",
+            std::process::id()
+        ));
+        std::fs::write(&archive, constructeur.into_inner().unwrap()).unwrap();
+
+        let entrees: Vec<_> = entrees_xml(archive.clone()).into_iter().collect();
+        std::fs::remove_file(&archive).unwrap();
+        let entree = entrees.into_iter().next().unwrap().unwrap();
+        let source = archive.join(&entree.chemin);
+        let decision = lire_decision(&entree.contenu[..], &source, false).unwrap();
+        let nom_archive = archive.to_string_lossy().to_string();
+        let document =
+            serde_json::to_value(situer_dans_archive(decision, &nom_archive, &entree)).unwrap();
+
+        assert_eq!(document["id"], "DTA_2300001");
+        assert_eq!(document["archive"], nom_archive.as_str());
+        assert_eq!(document["entree_archive"], "JADE/2023/DTA_2300001.xml");
+    }
+}
@@ -0,0 +1,150 @@
+use crate::champs::{TypeChamp, champs};
+use crate::configuration::ConfigurationModele;
+use crate::modele::{Decision, QualiteParsing};
+
+use serde_json::{Map, Value, json};
+
+fn type_elastic(type_champ: TypeChamp) -> Value {
+    match type_champ {
+        TypeChamp::Texte => json!({ "type": "text", "analyzer": "french" }),
+        TypeChamp::Date => json!({ "type": "date", "format": "yyyy-MM-dd" }),
+        TypeChamp::Keyword => json!({ "type": "keyword" }),
+        TypeChamp::Booleen => json!({ "type": "boolean" }),
+    }
+}
+
+/// Propriétés du mapping, dans l'ordre des champs sérialisés de `Decision` :
+/// les champs extraits prennent le type déclaré dans la table des champs, les
+/// champs calculés sont des `keyword`.
+pub fn generer_proprietes() -> Map<String, Value> {
+    let table = champs();
+    let mut exemple = Decision::default();
+    for nom in table.noms() {
+        if nom != "id" {
+            exemple.champs.insert(nom.clone(), Value::Null);
+        }
+    }
+    exemple.qualite_parsing = Some(QualiteParsing::Complete);
+    let exemple = serde_json::to_value(exemple).unwrap();
+    let mut proprietes: Map<String, Value> = exemple
+        .as_object()
+        .map(|champs| {
+            champs
+                .keys()
+                .map(|nom| {
+                    let type_champ = table.type_champ(nom).unwrap_or_default();
+                    (nom.clone(), type_elastic(type_champ))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if table.extra {
+        proprietes.insert("extra".to_string(), json!({ "type": "flattened" }));
+    }
+    proprietes
+}
+
+/// `decisions-{juridiction}-{annee_lecture}` devient `decisions-*-*`.
+pub fn motif_depuis_modele_index(modele_index: &str) -> String {
+    let mut motif = String::new();
+    let mut dans_variable = false;
+    for c in modele_index.to_lowercase().chars() {
+        match c {
+            '{' => {
+                dans_variable = true;
+                if !motif.ends_with('*') {
+                    motif.push('*');
+                }
+            }
+            '}' => dans_variable = false,
+            _ if !dans_variable => motif.push(c),
+            _ => {}
+        }
+    }
+    motif
+}
+
+/// Corps de `PUT _index_template/<nom>`.
+pub fn generer_modele_index(configuration: &ConfigurationModele, modele_index: &str) -> Value {
+    let motifs = if configuration.motifs.is_empty() {
+        vec![motif_depuis_modele_index(modele_index)]
+    } else {
+        configuration.motifs.clone()
+    };
+    json!({
+        "index_patterns": motifs,
+        "template": {
+            "settings": {
+                "number_of_shards": configuration.shards,
+                "number_of_replicas": configuration.replicas
+            },
+            "mappings": {
+                "properties": generer_proprietes()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduit_le_motif_du_modele_d_index() {
+        for (modele, attendu) in [
+            ("decisions-{juridiction}-{annee_lecture}", "decisions-*-*"),
+            ("{juridiction}_decisions", "*_decisions"),
+            ("Decisions-{juridiction}{annee_lecture}", "decisions-*"),
+            ("decisions", "decisions"),
+        ] {
+            assert_eq!(motif_depuis_modele_index(modele), attendu, "{}", modele);
+        }
+    }
+
+    #[test]
+    fn type_les_champs_extraits_et_calcules() {
+        let proprietes = generer_proprietes();
+        let type_de = |nom: &str| proprietes[nom]["type"].as_str();
+        assert_eq!(proprietes.keys().next().map(String::as_str), Some("id"));
+        for (nom, attendu) in [
+            ("id", "keyword"),
+            ("juridiction", "keyword"),
+            ("solution_normalisee", "keyword"),
+            ("date_mise_jour", "date"),
+            ("texte_integral", "text"),
+            ("serie_identique", "boolean"),
+            ("archive", "keyword"),
+            ("entree_archive", "keyword"),
+            ("qualite_parsing", "keyword"),
+        ] {
+            assert_eq!(type_de(nom), Some(attendu), "{}", nom);
+        }
+        assert_eq!(proprietes["date_mise_jour"]["format"], "yyyy-MM-dd");
+        assert_eq!(proprietes["texte_integral"]["analyzer"], "french");
+        assert!(!proprietes.contains_key("extra"));
+        assert!(!proprietes.contains_key("chemin_source"));
+    }
+
+    #[test]
+    fn genere_le_modele_d_index() {
+        let configuration = ConfigurationModele {
+            nom: "decisions".to_string(),
+            motifs: Vec::new(),
+            shards: 3,
+            replicas: 1,
+            installer: false,
+        };
+        let modele = generer_modele_index(&configuration, "{juridiction}_decisions");
+        assert_eq!(modele["index_patterns"], json!(["*_decisions"]));
+        assert_eq!(modele["template"]["settings"]["number_of_shards"], 3);
+        assert_eq!(modele["template"]["settings"]["number_of_replicas"], 1);
+        let modele = generer_modele_index(
+            &ConfigurationModele {
+                motifs: vec!["decisions-*".to_string()],
+                ..configuration
+            },
+            "{juridiction}_decisions",
+        );
+        assert_eq!(modele["index_patterns"], json!(["decisions-*"]));
+    }
+}
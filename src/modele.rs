@@ -1,50 +1,90 @@
 use dashmap::DashMap;
 use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
 use std::sync::LazyLock;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct Decision {
     pub id: String,
-    pub date_mise_jour: Option<String>,
-    pub code_juridiction: Option<String>,
-    pub numero_dossier: Option<String>,
-    pub code_publication: Option<String>,
-    pub nom_juridiction: Option<String>,
-    pub type_decision: Option<String>,
-    pub date_lecture: Option<String>,
-    pub solution: Option<String>,
-    pub solution_normalisee: Option<String>,
-    pub type_recours: Option<String>,
-    pub numero_ecli: Option<String>,
-    pub avocat_requerant: Option<String>,
-    pub formation_jugement: Option<String>,
-    pub date_audience: Option<String>,
-    pub numero_role: Option<String>,
-    pub texte_integral: Option<String>,
+    pub juridiction: Option<String>,
+    /// Champs extraits selon la table de `champs.rs`, dans son ordre.
+    #[serde(flatten)]
+    pub champs: Map<String, Value>,
+    /// Feuilles de `Document` absentes de la table des champs, avec `--extra`.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+    /// Archive `.tar.gz`/`.zip` d'où provient le fichier XML, le cas échéant.
+    pub archive: Option<String>,
+    /// Chemin du fichier XML dans l'archive.
+    pub entree_archive: Option<String>,
+    /// Renseigné avec `--recuperation`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qualite_parsing: Option<QualiteParsing>,
+    #[serde(skip)]
+    pub chemin_source: PathBuf,
+}
+
+/// Résultat de l'analyse en mode `--recuperation`, de la meilleure à la pire.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QualiteParsing {
+    /// Document bien formé.
+    #[default]
+    Complete,
+    /// Balises mal imbriquées, orphelines ou octets invalides : l'analyse
+    /// s'est poursuivie après l'anomalie.
+    Resynchronisee,
+    /// Fin de fichier ou erreur de syntaxe avant la fermeture du document :
+    /// seuls les champs déjà lus sont conservés.
+    Tronquee,
+}
+
+impl QualiteParsing {
+    pub fn libelle(self) -> &'static str {
+        match self {
+            QualiteParsing::Complete => "complete",
+            QualiteParsing::Resynchronisee => "resynchronisee",
+            QualiteParsing::Tronquee => "tronquee",
+        }
+    }
 }
 
 // static DEBUG_COUNT: AtomicUsize = AtomicUsize::new(0);
-pub static COMPTEURS_MANQUANTS: LazyLock<DashMap<&'static str, AtomicUsize>> =
-    LazyLock::new(|| DashMap::new());
-pub fn init_compteurs() {
-    for key in [
-        "date_mise_jour",
-        "code_juridiction",
-        "numero_dossier",
-        "code_publication",
-        "nom_juridiction",
-        "type_decision",
-        "date_lecture",
-        "solution",
-        "type_recours",
-        "numero_ecli",
-        "avocat_requerant",
-        "formation_jugement",
-        "date_audience",
-        "numero_role",
-        "texte_integral",
-    ] {
-        COMPTEURS_MANQUANTS.insert(key, AtomicUsize::new(0));
+/// Décisions où un champ apparaît plusieurs fois.
+#[derive(Default)]
+pub struct Repetition {
+    pub decisions: usize,
+    pub occurrences_max: usize,
+}
+
+pub static REPETITIONS: LazyLock<DashMap<String, Repetition>> = LazyLock::new(DashMap::new);
+
+pub fn enregistrer_repetition(champ: &str, occurrences: usize) {
+    if occurrences > 1 {
+        let mut repetition = REPETITIONS.entry(champ.to_string()).or_default();
+        repetition.decisions += 1;
+        repetition.occurrences_max = repetition.occurrences_max.max(occurrences);
     }
 }
+
+/// Fichiers analysés par encodage.
+pub static ENCODAGES: LazyLock<DashMap<String, AtomicUsize>> = LazyLock::new(DashMap::new);
+
+/// Décisions par qualité d'analyse, avec `--recuperation`.
+pub static QUALITES: LazyLock<DashMap<&'static str, AtomicUsize>> = LazyLock::new(DashMap::new);
+
+pub fn enregistrer_qualite(qualite: QualiteParsing) {
+    QUALITES
+        .entry(qualite.libelle())
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn enregistrer_encodage(encodage: &str) {
+    ENCODAGES
+        .entry(encodage.to_string())
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
@@ -1,146 +1,921 @@
-use crate::modele::{COMPTEURS_MANQUANTS, Decision};
-use crate::texte::{
-    est_chemin_autorise, normaliser_date, normaliser_espaces, normaliser_solution,
-    supprimer_balises_html,
+use crate::champs::{Normalisation, TableChamps, champs};
+use crate::modele::{
+    Decision, QualiteParsing, enregistrer_encodage, enregistrer_qualite, enregistrer_repetition,
 };
+use crate::qualite::{EtatChamp, enregistrer_decision};
+use crate::texte::{NonReconnue, enregistrer_non_reconnue, normaliser_juridiction};
+use html_escape::decode_html_entities;
 use quick_xml::Reader;
-use quick_xml::events::Event;
+use quick_xml::encoding::{Decoder, EncodingError};
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
-use std::io::BufReader;
-use std::sync::atomic::Ordering;
+use std::io::{BufRead, BufReader};
 
-pub fn incrementer_compteur_si_absent(val: &Option<String>, champ: &'static str) {
-    if val.is_none() {
-        COMPTEURS_MANQUANTS
-            .get(champ)
-            .unwrap()
-            .fetch_add(1, Ordering::Relaxed);
+/// Élément ouvert pendant l'analyse.
+struct Element {
+    chemin: String,
+    /// Texte assemblé à partir des événements Text, CData et GeneralRef,
+    /// affecté au champ à la fermeture de l'élément. Les entités y restent
+    /// telles quelles : la normalisation du champ les décode une seule fois.
+    texte: String,
+    /// Chemin retenu par la table des champs.
+    autorise: bool,
+    /// Descendant d'un élément retenu : son texte remonte vers ce dernier.
+    dans_champ: bool,
+    a_des_enfants: bool,
+}
+
+impl Element {
+    fn nom(&self) -> &str {
+        self.chemin.rsplit('/').next().unwrap_or_default()
+    }
+}
+
+/// Valeurs retenues au fil de l'analyse.
+#[derive(Default)]
+struct Valeurs {
+    /// Valeurs de chaque chemin de la table des champs, dans l'ordre du
+    /// document.
+    brutes: HashMap<String, Vec<String>>,
+    /// Chemins de la table présents sans texte.
+    vides: HashSet<String>,
+    extra: Map<String, Value>,
+}
+
+impl Valeurs {
+    /// `extra_possible` est faux pour les descendants d'un champ, dont le
+    /// texte appartient déjà à ce champ.
+    fn retenir(&mut self, table: &TableChamps, chemin: String, valeur: &str, extra_possible: bool) {
+        if table.est_chemin_autorise(&chemin) {
+            self.brutes
+                .entry(chemin)
+                .or_default()
+                .push(valeur.to_string());
+        } else if table.extra
+            && extra_possible
+            && let Some(cle) = cle_extra(&chemin)
+        {
+            ajouter_extra(
+                &mut self.extra,
+                cle,
+                decode_html_entities(valeur).into_owned(),
+            );
+        }
+    }
+
+    fn marquer_vide(&mut self, table: &TableChamps, chemin: String) {
+        if table.est_chemin_autorise(&chemin) {
+            self.vides.insert(chemin);
+        }
+    }
+}
+
+/// Nom de balise ou d'attribut transcodé depuis l'encodage du document.
+fn decoder_nom(decoder: Decoder, octets: &[u8]) -> String {
+    decoder
+        .decode(octets)
+        .map(|nom| nom.into_owned())
+        .unwrap_or_else(|_| String::from_utf8_lossy(octets).into_owned())
+}
+
+/// Chemin d'un élément ouvert sous le sommet de la pile, et s'il descend d'un
+/// champ retenu.
+fn chemin_enfant(pile: &mut [Element], tag: &str) -> (String, bool) {
+    match pile.last_mut() {
+        Some(parent) => {
+            parent.a_des_enfants = true;
+            (
+                format!("{}/{}", parent.chemin, tag),
+                parent.autorise || parent.dans_champ,
+            )
+        }
+        None => (tag.to_string(), false),
+    }
+}
+
+/// Les attributs sont adressés comme `Document/Dossier/@type`.
+fn lire_attributs(
+    element: &BytesStart,
+    chemin: &str,
+    dans_champ: bool,
+    table: &TableChamps,
+    valeurs: &mut Valeurs,
+) {
+    for attribut in element.attributes().flatten() {
+        let nom = decoder_nom(element.decoder(), attribut.key.as_ref());
+        let valeur = decoder_nom(element.decoder(), &attribut.value);
+        let valeur = valeur.trim();
+        let chemin = format!("{}/@{}", chemin, nom);
+        if valeur.is_empty() {
+            valeurs.marquer_vide(table, chemin);
+        } else {
+            valeurs.retenir(table, chemin, valeur, !dans_champ);
+        }
+    }
+}
+
+/// Ferme le sommet de la pile et retient son texte.
+fn fermer(pile: &mut Vec<Element>, table: &TableChamps, valeurs: &mut Valeurs) {
+    let Some(element) = pile.pop() else {
+        return;
+    };
+    // Le texte des descendants d'un champ fait partie du champ ; seuls les
+    // éléments de bloc le coupent en lignes.
+    if element.dans_champ
+        && let Some(parent) = pile.last_mut()
+    {
+        parent.texte.push_str(&element.texte);
+        if !est_en_ligne_en_html(element.nom()) {
+            parent.texte.push('\n');
+        }
+    }
+    let val = element.texte.trim();
+    if val.is_empty() {
+        valeurs.marquer_vide(table, element.chemin);
+    } else if element.autorise || !element.a_des_enfants {
+        valeurs.retenir(table, element.chemin, val, !element.dans_champ);
+    }
+}
+
+/// Mode `--recuperation` : les anomalies dégradent la qualité de la décision
+/// au lieu de la rejeter.
+struct Recuperation {
+    active: bool,
+    qualite: QualiteParsing,
+}
+
+impl Recuperation {
+    fn signaler(&mut self, qualite: QualiteParsing) {
+        self.qualite = self.qualite.max(qualite);
+    }
+
+    /// Les octets invalides pour l'encodage sont remplacés par U+FFFD.
+    /// `debut` est la position du premier octet de `octets` dans le fichier.
+    fn texte(
+        &mut self,
+        decode: Result<String, EncodingError>,
+        octets: &[u8],
+        debut: u64,
+    ) -> Result<String, ErreurLecture> {
+        match decode {
+            Ok(texte) => Ok(texte),
+            Err(_) if self.active => {
+                self.signaler(QualiteParsing::Resynchronisee);
+                Ok(String::from_utf8_lossy(octets).into_owned())
+            }
+            Err(erreur) => {
+                // Les encodages à un octet ne rejettent aucun octet : seul
+                // l'UTF-8 peut échouer au milieu du texte.
+                let decalage = std::str::from_utf8(octets)
+                    .err()
+                    .map_or(0, |e| e.valid_up_to() as u64);
+                Err(ErreurLecture::depuis_xml(erreur.into(), debut + decalage))
+            }
+        }
+    }
+}
+
+/// `<br/>` et autres balises vides séparent les lignes du texte.
+fn separer_lignes(pile: &mut [Element]) {
+    if let Some(courant) = pile.last_mut()
+        && !courant.texte.is_empty()
+    {
+        courant.texte.push('\n');
+    }
+}
+
+fn est_vide_en_html(tag: &str) -> bool {
+    matches!(
+        tag.to_ascii_lowercase().as_str(),
+        "br" | "hr" | "img" | "col" | "wbr"
+    )
+}
+
+/// `Article 1<sup>er</sup>` reste sur une ligne.
+fn est_en_ligne_en_html(tag: &str) -> bool {
+    matches!(
+        tag.to_ascii_lowercase().as_str(),
+        "a" | "abbr"
+            | "b"
+            | "big"
+            | "cite"
+            | "code"
+            | "em"
+            | "font"
+            | "i"
+            | "mark"
+            | "q"
+            | "s"
+            | "small"
+            | "span"
+            | "strike"
+            | "strong"
+            | "sub"
+            | "sup"
+            | "tt"
+            | "u"
+    )
+}
+
+fn ajouter_texte(pile: &mut [Element], morceau: &str) {
+    if let Some(courant) = pile.last_mut() {
+        courant.texte.push_str(morceau);
+    }
+}
+
+/// Une balise répétée devient une liste de ses valeurs.
+fn ajouter_extra(extra: &mut Map<String, Value>, cle: String, valeur: String) {
+    match extra.get_mut(&cle) {
+        Some(Value::Array(valeurs)) => valeurs.push(Value::String(valeur)),
+        Some(precedente) => {
+            let premiere = precedente.take();
+            *precedente = Value::Array(vec![premiere, Value::String(valeur)]);
+        }
+        None => {
+            extra.insert(cle, Value::String(valeur));
+        }
     }
 }
 
-pub fn lire_decision_xml(path: &std::path::Path) -> Option<Decision> {
-    let file = File::open(path).ok()?;
-    let mut reader = Reader::from_reader(BufReader::new(file));
-    reader.config_mut().trim_text(true);
+/// `Document/Dossier/Numero_Affaire` devient `Dossier.Numero_Affaire`, et
+/// `Document/Dossier/@type` devient `Dossier.@type`.
+fn cle_extra(chemin: &str) -> Option<String> {
+    chemin
+        .strip_prefix("Document/")
+        .map(|relatif| relatif.replace('/', "."))
+}
+
+/// Raison pour laquelle un fichier n'a pas produit de décision.
+#[derive(Debug)]
+pub enum ErreurLecture {
+    Io(String),
+    /// XML mal formé, à la position donnée en octets.
+    XmlMalforme {
+        position: u64,
+        message: String,
+    },
+    IdentifiantManquant,
+    /// Octets invalides pour l'encodage du document.
+    Decodage {
+        position: u64,
+        message: String,
+    },
+    /// Archive tronquée ou corrompue : ses entrées suivantes n'ont pas été lues.
+    ArchiveIllisible(String),
+}
+
+impl ErreurLecture {
+    /// Catégorie utilisée pour les totaux et le rapport des rejets.
+    pub fn categorie(&self) -> &'static str {
+        match self {
+            ErreurLecture::Io(_) => "io",
+            ErreurLecture::XmlMalforme { .. } => "xml_malforme",
+            ErreurLecture::IdentifiantManquant => "identifiant_manquant",
+            ErreurLecture::Decodage { .. } => "decodage",
+            ErreurLecture::ArchiveIllisible(_) => "archive_illisible",
+        }
+    }
+
+    pub fn position(&self) -> Option<u64> {
+        match self {
+            ErreurLecture::XmlMalforme { position, .. }
+            | ErreurLecture::Decodage { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    fn depuis_xml(erreur: quick_xml::Error, position: u64) -> Self {
+        match erreur {
+            quick_xml::Error::Io(e) => ErreurLecture::Io(e.to_string()),
+            quick_xml::Error::Encoding(e) => ErreurLecture::Decodage {
+                position,
+                message: e.to_string(),
+            },
+            autre => ErreurLecture::XmlMalforme {
+                position,
+                message: autre.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ErreurLecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErreurLecture::Io(message) => write!(f, "lecture impossible: {}", message),
+            ErreurLecture::XmlMalforme { position, message } => {
+                write!(f, "XML mal formé à l'octet {}: {}", position, message)
+            }
+            ErreurLecture::IdentifiantManquant => write!(f, "Identification absente"),
+            ErreurLecture::Decodage { position, message } => {
+                write!(f, "décodage impossible à l'octet {}: {}", position, message)
+            }
+            ErreurLecture::ArchiveIllisible(message) => write!(f, "archive illisible: {}", message),
+        }
+    }
+}
+
+pub fn lire_decision_xml(
+    path: &std::path::Path,
+    recuperation: bool,
+) -> Result<Decision, ErreurLecture> {
+    let file = File::open(path).map_err(|e| ErreurLecture::Io(e.to_string()))?;
+    lire_decision(BufReader::new(file), path, recuperation)
+}
+
+/// Analyse un document XML déjà ouvert ; `path` n'est conservé que comme
+/// provenance de la décision.
+///
+/// Avec `recuperation`, une balise fermante referme les éléments restés
+/// ouverts jusqu'à son ouvrante (ou est ignorée si elle n'en a pas), et une
+/// erreur de syntaxe ou une fin de fichier prématurée referme tout le
+/// document : la décision garde les champs lus et sa `qualite_parsing`.
+pub fn lire_decision<R: BufRead>(
+    lecteur: R,
+    path: &std::path::Path,
+    recuperation: bool,
+) -> Result<Decision, ErreurLecture> {
+    lire_decision_selon(lecteur, path, recuperation, champs())
+}
+
+/// `lire_decision` avec une autre table que celle installée.
+fn lire_decision_selon<R: BufRead>(
+    lecteur: R,
+    path: &std::path::Path,
+    recuperation: bool,
+    table: &TableChamps,
+) -> Result<Decision, ErreurLecture> {
+    let mut reader = Reader::from_reader(lecteur);
+    reader.config_mut().check_end_names = !recuperation;
+    reader.config_mut().allow_unmatched_ends = recuperation;
+    let mut recuperation = Recuperation {
+        active: recuperation,
+        qualite: QualiteParsing::Complete,
+    };
     let mut buf = Vec::new();
-    let mut pile = Vec::new();
-    let mut texte = String::new();
-    let mut dans_texte = false;
-    let mut id = None;
-    let mut date_mise_jour = None;
-    let mut code_juridiction = None;
-    let mut numero_dossier = None;
-    let mut code_publication = None;
-    let mut nom_juridiction = None;
-    let mut type_decision = None;
-    let mut date_lecture = None;
-    let mut solution = None;
-    let mut type_recours = None;
-    let mut numero_ecli = None;
-    let mut avocat_requerant = None;
-    let mut formation_jugement = None;
-    let mut date_audience = None;
-    let mut numero_role = None;
+    let mut pile: Vec<Element> = Vec::new();
+    let mut valeurs = Valeurs::default();
+    let mut encodage_declare = None;
     loop {
+        let debut = reader.buffer_position();
         match reader.read_event_into(&mut buf) {
+            Ok(Event::Decl(e)) => {
+                encodage_declare = e
+                    .encoding()
+                    .and_then(Result::ok)
+                    .map(|encodage| String::from_utf8_lossy(&encodage).to_uppercase());
+            }
             Ok(Event::Start(e)) => {
-                let tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
-                pile.push(tag.clone());
-                if pile.join("/") == "Document/Decision/Texte_Integral" {
-                    dans_texte = true;
+                let tag = decoder_nom(reader.decoder(), e.name().as_ref());
+                let (chemin, dans_champ) = chemin_enfant(&mut pile, &tag);
+                lire_attributs(&e, &chemin, dans_champ, table, &mut valeurs);
+                // <br>, <hr>... ne sont jamais refermées dans le HTML des
+                // décisions.
+                if recuperation.active && dans_champ && est_vide_en_html(&tag) {
+                    recuperation.signaler(QualiteParsing::Resynchronisee);
+                    separer_lignes(&mut pile);
+                    buf.clear();
+                    continue;
                 }
+                pile.push(Element {
+                    autorise: table.est_chemin_autorise(&chemin),
+                    chemin,
+                    texte: String::new(),
+                    dans_champ,
+                    a_des_enfants: false,
+                });
             }
-            Ok(Event::End(_)) => {
-                if pile.join("/") == "Document/Decision/Texte_Integral" {
-                    dans_texte = false;
-                }
-                pile.pop();
+            Ok(Event::Empty(e)) => {
+                let tag = decoder_nom(reader.decoder(), e.name().as_ref());
+                let (chemin, dans_champ) = chemin_enfant(&mut pile, &tag);
+                lire_attributs(&e, &chemin, dans_champ, table, &mut valeurs);
+                valeurs.marquer_vide(table, chemin);
+                separer_lignes(&mut pile);
             }
             Ok(Event::Text(e)) => {
-                let chemin = pile.join("/");
-                let val = e.decode().ok()?.to_string();
-                if dans_texte {
-                    texte.push_str(&val);
-                    texte.push('\n');
-                }
-                if !est_chemin_autorise(&chemin) {
-                    buf.clear();
-                    continue;
+                let decode = e.decode().map(|texte| texte.into_owned());
+                let texte = recuperation.texte(decode, &e, debut)?;
+                ajouter_texte(&mut pile, &texte);
+            }
+            Ok(Event::CData(e)) => {
+                let decode = e.decode().map(|texte| texte.into_owned());
+                let texte = recuperation.texte(decode, &e, debut + "<![CDATA[".len() as u64)?;
+                ajouter_texte(&mut pile, &texte);
+            }
+            // Conservée telle quelle, décodée avec le reste du texte.
+            Ok(Event::GeneralRef(e)) => {
+                let decode = e.decode().map(|nom| nom.into_owned());
+                let nom = recuperation.texte(decode, &e, debut + 1)?;
+                ajouter_texte(&mut pile, &format!("&{};", nom));
+            }
+            Ok(Event::End(e)) if recuperation.active => {
+                let tag = decoder_nom(reader.decoder(), e.name().as_ref());
+                match pile.iter().rposition(|element| element.nom() == tag) {
+                    Some(index) => {
+                        if index + 1 < pile.len() {
+                            recuperation.signaler(QualiteParsing::Resynchronisee);
+                        }
+                        while pile.len() > index {
+                            fermer(&mut pile, table, &mut valeurs);
+                        }
+                    }
+                    None => recuperation.signaler(QualiteParsing::Resynchronisee),
                 }
-                match chemin.as_str() {
-                    "Document/Donnees_Techniques/Identification" => id = Some(val),
-                    "Document/Donnees_Techniques/Date_Mise_Jour" => date_mise_jour = Some(val),
-                    "Document/Dossier/Code_Juridiction" => code_juridiction = Some(val),
-                    "Document/Dossier/Numero_Dossier" => numero_dossier = Some(val),
-                    "Document/Dossier/Code_Publication" => code_publication = Some(val),
-                    "Document/Dossier/Nom_Juridiction" => nom_juridiction = Some(val),
-                    "Document/Dossier/Type_Decision" => type_decision = Some(val),
-                    "Document/Dossier/Date_Lecture" => date_lecture = Some(val),
-                    "Document/Dossier/Solution" => solution = Some(val),
-                    "Document/Dossier/Type_Recours" => type_recours = Some(val),
-                    "Document/Dossier/Numero_ECLI" => numero_ecli = Some(val),
-                    "Document/Dossier/Avocat_Requerant" => avocat_requerant = Some(val),
-                    "Document/Audience/Formation_Jugement" => formation_jugement = Some(val),
-                    "Document/Audience/Date_Audience" => date_audience = Some(val),
-                    "Document/Audience/Numero_Role" => numero_role = Some(val),
-                    _ => {}
+            }
+            Ok(Event::End(_)) => {
+                if pile.is_empty() {
+                    break;
                 }
+                fermer(&mut pile, table, &mut valeurs);
             }
             Ok(Event::Eof) => break,
-            Err(_) => break,
+            Err(quick_xml::Error::IllFormed(_)) if recuperation.active => {
+                recuperation.signaler(QualiteParsing::Resynchronisee);
+            }
+            Err(erreur) if recuperation.active && !matches!(erreur, quick_xml::Error::Io(_)) => {
+                recuperation.signaler(QualiteParsing::Tronquee);
+                break;
+            }
+            Err(erreur) => {
+                return Err(ErreurLecture::depuis_xml(erreur, reader.error_position()));
+            }
             _ => {}
         }
         buf.clear();
     }
-    incrementer_compteur_si_absent(&date_mise_jour, "date_mise_jour");
-    incrementer_compteur_si_absent(&code_juridiction, "code_juridiction");
-    incrementer_compteur_si_absent(&numero_dossier, "numero_dossier");
-    incrementer_compteur_si_absent(&code_publication, "code_publication");
-    incrementer_compteur_si_absent(&nom_juridiction, "nom_juridiction");
-    incrementer_compteur_si_absent(&type_decision, "type_decision");
-    incrementer_compteur_si_absent(&type_decision, "texte_integral");
-    incrementer_compteur_si_absent(&date_lecture, "date_lecture");
-    incrementer_compteur_si_absent(&solution, "solution");
-    incrementer_compteur_si_absent(&type_recours, "type_recours");
-    incrementer_compteur_si_absent(&numero_ecli, "numero_ecli");
-    incrementer_compteur_si_absent(&avocat_requerant, "avocat_requerant");
-    incrementer_compteur_si_absent(&formation_jugement, "formation_jugement");
-    incrementer_compteur_si_absent(&date_audience, "date_audience");
-    incrementer_compteur_si_absent(&numero_role, "numero_role");
-    let id = id?;
-    let solution_normalisee = solution.as_ref().map(|s| normaliser_solution(s));
-    let texte_integral = if texte.is_empty() {
-        incrementer_compteur_si_absent(&None, "texte_integral");
-        None
-    } else {
-        Some(normaliser_espaces(&supprimer_balises_html(&texte)))
-    };
-    // let index = DEBUG_COUNT.fetch_add(1, Ordering::Relaxed);
-    // if index < 2 {
-    //     println!("DEBUG {}", path.display());
-    //     println!(
-    //         "DEBUG {} -> texte size {:?}",
-    //         path.display(),
-    //         texte_integral.as_ref().map(|s| s.len())
-    //     );
-    // }
-    Some(Decision {
+    if recuperation.active && !pile.is_empty() {
+        recuperation.signaler(QualiteParsing::Tronquee);
+        while !pile.is_empty() {
+            fermer(&mut pile, table, &mut valeurs);
+        }
+    }
+    // Libellé déclaré (ISO-8859-1 est décodé comme windows-1252), sinon
+    // l'encodage détecté par le BOM, UTF-8 par défaut.
+    enregistrer_encodage(
+        &encodage_declare.unwrap_or_else(|| reader.decoder().encoding().name().to_uppercase()),
+    );
+    let mut id = None;
+    let mut champs_decision = Map::new();
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    // État de chaque champ, dans l'ordre de la table.
+    let mut etats: Vec<(&str, EtatChamp)> = Vec::new();
+    // Comptées seulement si la décision est acceptée.
+    let mut non_reconnues: Vec<NonReconnue> = Vec::new();
+    for definition in &table.definitions {
+        let brutes = valeurs
+            .brutes
+            .get(&definition.chemin)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        *occurrences.entry(definition.nom.as_str()).or_default() += brutes.len();
+        if definition.nom == "id" {
+            id = brutes
+                .last()
+                .map(|id| decode_html_entities(id).into_owned());
+            continue;
+        }
+        // Toutes les occurrences d'un champ multiple, sinon la dernière.
+        let retenues = if definition.multiple {
+            brutes
+        } else {
+            &brutes[brutes.len().saturating_sub(1)..]
+        };
+        let mut normalisees: Vec<Value> = retenues
+            .iter()
+            .filter_map(|brute| {
+                definition
+                    .normalisation
+                    .appliquer(brute, &mut non_reconnues)
+            })
+            .collect();
+        let etat = if !normalisees.is_empty() {
+            EtatChamp::Present
+        } else if !brutes.is_empty() && definition.normalisation == Normalisation::Date {
+            EtatChamp::DateInvalide
+        } else if !brutes.is_empty() || valeurs.vides.contains(&definition.chemin) {
+            EtatChamp::Vide
+        } else {
+            EtatChamp::Manquant
+        };
+        etats.push((definition.nom.as_str(), etat));
+        let valeur = if !definition.multiple {
+            normalisees.pop().unwrap_or(Value::Null)
+        } else if normalisees.is_empty() {
+            Value::Null
+        } else {
+            Value::Array(normalisees)
+        };
+        champs_decision.insert(definition.nom.clone(), valeur);
+    }
+    let id = id.ok_or(ErreurLecture::IdentifiantManquant)?;
+    non_reconnues.into_iter().for_each(enregistrer_non_reconnue);
+    if recuperation.active {
+        enregistrer_qualite(recuperation.qualite);
+    }
+    for (nom, nombre) in occurrences {
+        enregistrer_repetition(nom, nombre);
+    }
+    let code_juridiction = champs_decision
+        .get("code_juridiction")
+        .and_then(Value::as_str);
+    let juridiction = normaliser_juridiction(code_juridiction, &id);
+    enregistrer_decision(
+        juridiction.as_deref(),
+        etats
+            .iter()
+            .map(|(nom, etat)| (*nom, *etat, &champs_decision[*nom])),
+    );
+    Ok(Decision {
+        juridiction,
         id,
-        date_mise_jour: date_mise_jour.as_deref().and_then(normaliser_date),
-        code_juridiction,
-        numero_dossier,
-        code_publication,
-        nom_juridiction,
-        type_decision,
-        date_lecture: date_lecture.as_deref().and_then(normaliser_date),
-        solution,
-        solution_normalisee,
-        type_recours,
-        numero_ecli,
-        avocat_requerant,
-        formation_jugement,
-        date_audience: date_audience.as_deref().and_then(normaliser_date),
-        numero_role,
-        texte_integral,
+        champs: champs_decision,
+        extra: valeurs.extra,
+        archive: None,
+        entree_archive: None,
+        qualite_parsing: recuperation.active.then_some(recuperation.qualite),
+        chemin_source: path.to_path_buf(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texte::{formations_non_reconnues, types_recours_non_reconnus};
+    use std::path::Path;
+
+    fn lire(dossier: &str, decision: &str) -> Decision {
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Document>\n  <Donnees_Techniques>\n    <Identification>DTA_2400001_20240105</Identification>\n  </Donnees_Techniques>\n  <Dossier>{}</Dossier>\n  <Decision>{}</Decision>\n</Document>",
+            dossier, decision
+        );
+        lire_decision(xml.as_bytes(), Path::new("test.xml"), false).unwrap()
+    }
+
+    fn champ<'a>(decision: &'a Decision, nom: &str) -> Option<&'a str> {
+        decision.champs.get(nom).and_then(Value::as_str)
+    }
+
+    /// Table réduite à `id` et aux champs donnés.
+    fn table(champs: &str, extra: bool) -> TableChamps {
+        let mut table = TableChamps::depuis_toml(&format!(
+            "[[champ]]\nchemin = \"Document/Donnees_Techniques/Identification\"\nnom = \"id\"\n{}",
+            champs
+        ))
+        .unwrap();
+        table.extra = extra;
+        table
+    }
+
+    #[test]
+    fn lit_le_texte_simple_sans_espaces_autour() {
+        let decision = lire(
+            "\n    <Solution>\n      Rejet\n    </Solution>\n",
+            "<Texte_Integral>Le tribunal rejette.</Texte_Integral>",
+        );
+        assert_eq!(decision.id, "DTA_2400001_20240105");
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Le tribunal rejette.")
+        );
+    }
+
+    #[test]
+    fn lit_le_cdata_du_texte_integral_et_des_metadonnees() {
+        let decision = lire(
+            "<Nom_Juridiction><![CDATA[Tribunal administratif de Paris]]></Nom_Juridiction>",
+            "<Texte_Integral><![CDATA[<p>Vu la requête</p><p>Considérant que</p>]]></Texte_Integral>",
+        );
+        assert_eq!(
+            champ(&decision, "nom_juridiction"),
+            Some("Tribunal administratif de Paris")
+        );
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Vu la requête\nConsidérant que")
+        );
+    }
+
+    #[test]
+    fn resout_les_entites_predefinies() {
+        let decision = lire(
+            "<Nom_Juridiction>SCP A &amp; B &lt;Paris&gt;</Nom_Juridiction>",
+            "<Texte_Integral>Le tribunal &amp; la cour &quot;ceci&quot; &apos;ici&apos;</Texte_Integral>",
+        );
+        assert_eq!(
+            champ(&decision, "nom_juridiction"),
+            Some("SCP A & B <Paris>")
+        );
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Le tribunal & la cour \"ceci\" 'ici'")
+        );
+    }
+
+    #[test]
+    fn resout_les_references_numeriques() {
+        let decision = lire(
+            "<Type_Recours>Exc&#232;s de pouvoir</Type_Recours>",
+            "<Texte_Integral>R&#xE9;publique fran&#231;aise</Texte_Integral>",
+        );
+        assert_eq!(champ(&decision, "type_recours"), Some("Excès de pouvoir"));
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("République française")
+        );
+    }
+
+    #[test]
+    fn resout_les_entites_html_et_conserve_les_inconnues() {
+        let decision = lire(
+            "<Solution>Satisfaction&nbsp;totale</Solution><Type_Decision>D&eacute;cision &inconnue;</Type_Decision>",
+            "",
+        );
+        assert_eq!(
+            champ(&decision, "solution"),
+            Some("Satisfaction\u{a0}totale")
+        );
+        assert_eq!(
+            champ(&decision, "type_decision"),
+            Some("Décision &inconnue;")
+        );
+    }
+
+    #[test]
+    fn assemble_texte_cdata_et_entites_melanges() {
+        let decision = lire(
+            "<Numero_Dossier>24&#48;0001<![CDATA[/5]]></Numero_Dossier>",
+            "<Texte_Integral>Article 1<sup>er</sup> &amp; <![CDATA[<b>suite</b>]]> fin</Texte_Integral>",
+        );
+        assert_eq!(champ(&decision, "numero_dossier"), Some("2400001/5"));
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Article 1er & suite fin")
+        );
+    }
+
+    #[test]
+    fn decode_les_entites_une_seule_fois() {
+        let decision = lire(
+            "<Numero_Dossier>24&amp;lt;01</Numero_Dossier>",
+            "<Texte_Integral>Article &lt;abrogé&gt; &amp;lt; <![CDATA[<p>a &amp; b</p>]]></Texte_Integral>",
+        );
+        assert_eq!(champ(&decision, "numero_dossier"), Some("24&lt;01"));
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Article <abrogé> &lt; a & b")
+        );
+    }
+
+    #[test]
+    fn ne_coupe_pas_les_lignes_aux_balises_en_ligne() {
+        let decision = lire(
+            "",
+            "<Texte_Integral><p>Vu la requête <i>n° 1</i>, <b>rejetée</b> le 1<sup>er</sup> mars.</p><p>Fin.</p></Texte_Integral>",
+        );
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Vu la requête n° 1, rejetée le 1er mars.\nFin.")
+        );
+    }
+
+    #[test]
+    fn garde_le_texte_des_paragraphes_imbriques() {
+        let decision = lire(
+            "",
+            "<Texte_Integral>\n  <p>Premier paragraphe.</p>\n  <div><p>Second<br/>paragraphe.</p></div>\n</Texte_Integral>",
+        );
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Premier paragraphe.\nSecond\nparagraphe.")
+        );
+    }
+
+    #[test]
+    fn conserve_toutes_les_occurrences_des_champs_multiples() {
+        let decision = lire(
+            "<Avocat_Requerant>Me A</Avocat_Requerant><Solution>Non-lieu</Solution><Avocat_Requerant>Me &amp; B</Avocat_Requerant><Solution>Rejet</Solution>",
+            "",
+        );
+        assert_eq!(
+            decision.champs["avocat_requerant"],
+            serde_json::json!(["Me A", "Me & B"])
+        );
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+    }
+
+    #[test]
+    fn ignore_les_attributs_absents_de_la_table() {
+        let decision = lire(
+            "<Solution type=\"fond\">Rejet</Solution>",
+            "<Texte_Integral><p class=\"x\">Texte</p></Texte_Integral>",
+        );
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(champ(&decision, "texte_integral"), Some("Texte"));
+        assert!(decision.extra.is_empty());
+    }
+
+    #[test]
+    fn conserve_les_feuilles_inconnues_dans_extra() {
+        let table = table(
+            "[[champ]]\nchemin = \"Document/Dossier/Solution\"\nnom = \"solution\"\n",
+            true,
+        );
+        let xml = "<Document><Donnees_Techniques><Identification>DTA_1</Identification><Lot>JADE_1</Lot></Donnees_Techniques><Dossier type=\"recours\"><Solution>Rejet</Solution><Numero_Affaire>42</Numero_Affaire><Avocat>Me A</Avocat><Avocat>Me &amp;amp; B</Avocat></Dossier><Decision><Texte_Integral><p>Texte</p></Texte_Integral></Decision></Document>";
+        let decision =
+            lire_decision_selon(xml.as_bytes(), Path::new("test.xml"), false, &table).unwrap();
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(
+            Value::Object(decision.extra),
+            serde_json::json!({
+                "Donnees_Techniques.Lot": "JADE_1",
+                "Dossier.@type": "recours",
+                "Dossier.Numero_Affaire": "42",
+                "Dossier.Avocat": ["Me A", "Me &amp; B"],
+                "Decision.Texte_Integral.p": "Texte",
+            })
+        );
+    }
+
+    #[test]
+    fn remplit_un_champ_depuis_un_attribut() {
+        let table = table(
+            "[[champ]]\nchemin = \"Document/Dossier/@type\"\nnom = \"type_dossier\"\n\
+             [[champ]]\nchemin = \"Document/Dossier/@date\"\nnom = \"date_dossier\"\ntype = \"date\"\nnormalisation = \"date\"\n",
+            false,
+        );
+        let xml = "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques><Dossier type=\" recours &amp; appel \" date=\"05/01/2024\"/></Document>";
+        let decision =
+            lire_decision_selon(xml.as_bytes(), Path::new("test.xml"), false, &table).unwrap();
+        assert_eq!(champ(&decision, "type_dossier"), Some("recours & appel"));
+        assert_eq!(champ(&decision, "date_dossier"), Some("2024-01-05"));
+        assert!(decision.extra.is_empty());
+    }
+
+    #[test]
+    fn transcode_les_fichiers_iso_8859_1() {
+        let mut xml = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\n<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques><Dossier><Type_Recours>Exc".to_vec();
+        xml.push(0xE8);
+        xml.extend_from_slice(b"s de pouvoir</Type_Recours></Dossier></Document>");
+        let decision = lire_decision(&xml[..], Path::new("test.xml"), false).unwrap();
+        assert_eq!(champ(&decision, "type_recours"), Some("Excès de pouvoir"));
+    }
+
+    #[test]
+    fn rejette_le_xml_mal_forme_avec_sa_position() {
+        let xml = "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Dossier></Document>";
+        match lire_decision(xml.as_bytes(), Path::new("test.xml"), false) {
+            // Début de la balise fermante </Dossier>.
+            Err(ErreurLecture::XmlMalforme { position, .. }) => assert_eq!(position, 68),
+            autre => panic!("{:?}", autre.map(|d| d.id)),
+        }
+    }
+
+    #[test]
+    fn rejette_le_document_sans_identification() {
+        let xml = "<Document><Dossier><Solution>Rejet</Solution></Dossier></Document>";
+        assert!(matches!(
+            lire_decision(xml.as_bytes(), Path::new("test.xml"), false),
+            Err(ErreurLecture::IdentifiantManquant)
+        ));
+    }
+
+    #[test]
+    fn rejette_les_octets_invalides_pour_l_encodage() {
+        let mut xml = b"<Document><Donnees_Techniques><Identification>DTA_".to_vec();
+        xml.push(0xE9);
+        xml.extend_from_slice(b"</Identification></Donnees_Techniques></Document>");
+        assert!(matches!(
+            lire_decision(&xml[..], Path::new("test.xml"), false),
+            Err(ErreurLecture::Decodage { .. })
+        ));
+    }
+
+    #[test]
+    fn compte_les_valeurs_non_reconnues_des_decisions_acceptees() {
+        let xml = |identification: &str, suffixe: &str| {
+            format!(
+                "<Document><Donnees_Techniques>{}</Donnees_Techniques><Dossier><Type_Recours>Recours {}</Type_Recours></Dossier><Audience><Formation_Jugement>Greffe {}</Formation_Jugement></Audience></Document>",
+                identification, suffixe, suffixe
+            )
+        };
+        let compte = |suffixe: &str| {
+            let type_recours = format!("Recours {}", suffixe);
+            let formation = format!("Greffe {}", suffixe);
+            (
+                types_recours_non_reconnus()
+                    .iter()
+                    .any(|(valeur, _)| *valeur == type_recours),
+                formations_non_reconnues()
+                    .iter()
+                    .any(|(valeur, _)| *valeur == formation),
+            )
+        };
+        let rejetee = xml("", "sans identifiant");
+        assert!(lire_decision(rejetee.as_bytes(), Path::new("test.xml"), false).is_err());
+        assert_eq!(compte("sans identifiant"), (false, false));
+        let acceptee = xml("<Identification>DTA_1</Identification>", "accepté");
+        assert!(lire_decision(acceptee.as_bytes(), Path::new("test.xml"), false).is_ok());
+        assert_eq!(compte("accepté"), (true, true));
+    }
+
+    #[test]
+    fn situe_l_octet_invalide_dans_le_fichier() {
+        for (avant, apres) in [
+            (
+                "<Document><Donnees_Techniques><Identification>DTA_",
+                "</Identification>",
+            ),
+            (
+                "<Document><Donnees_Techniques><Identification><![CDATA[DTA_",
+                "]]></Identification>",
+            ),
+        ] {
+            let mut xml = avant.as_bytes().to_vec();
+            xml.push(0xE9);
+            xml.extend_from_slice(apres.as_bytes());
+            xml.extend_from_slice(b"</Donnees_Techniques></Document>");
+            let Err(erreur) = lire_decision(&xml[..], Path::new("test.xml"), false) else {
+                panic!("décision acceptée malgré l'octet invalide");
+            };
+            assert_eq!(erreur.categorie(), "decodage");
+            assert_eq!(erreur.position(), Some(avant.len() as u64));
+        }
+    }
+
+    fn recuperer(xml: &str) -> Decision {
+        lire_decision(xml.as_bytes(), Path::new("test.xml"), true).unwrap()
+    }
+
+    #[test]
+    fn recupere_le_html_non_ferme_du_texte_integral() {
+        let decision = recuperer(
+            "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques><Dossier><Solution>Rejet</Solution></Dossier><Decision><Texte_Integral><p>Vu la requête<br>Considérant que</Texte_Integral></Decision><Audience><Numero_Role>12</Numero_Role></Audience></Document>",
+        );
+        assert_eq!(
+            decision.qualite_parsing,
+            Some(QualiteParsing::Resynchronisee)
+        );
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Vu la requête\nConsidérant que")
+        );
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(champ(&decision, "numero_role"), Some("12"));
+    }
+
+    #[test]
+    fn ignore_les_balises_fermantes_orphelines_en_recuperation() {
+        let decision = recuperer(
+            "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Dossier></Donnees_Techniques><Dossier><Solution>Rejet</Solution></Dossier></Document>",
+        );
+        assert_eq!(
+            decision.qualite_parsing,
+            Some(QualiteParsing::Resynchronisee)
+        );
+        assert_eq!(decision.id, "DTA_1");
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+    }
+
+    #[test]
+    fn garde_les_champs_lus_d_un_fichier_tronque() {
+        let decision = recuperer(
+            "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques><Dossier><Solution>Rejet</Solution></Dossier><Decision><Texte_Integral><p>Vu la requête</p><p>Considérant",
+        );
+        assert_eq!(decision.qualite_parsing, Some(QualiteParsing::Tronquee));
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(
+            champ(&decision, "texte_integral"),
+            Some("Vu la requête\nConsidérant")
+        );
+    }
+
+    #[test]
+    fn s_arrete_a_l_erreur_de_syntaxe_en_recuperation() {
+        let decision = recuperer(
+            "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques><Dossier><Solution>Rejet</Solution><Type_Recours",
+        );
+        assert_eq!(decision.qualite_parsing, Some(QualiteParsing::Tronquee));
+        assert_eq!(champ(&decision, "solution"), Some("Rejet"));
+        assert_eq!(champ(&decision, "type_recours"), None);
+    }
+
+    #[test]
+    fn marque_complet_le_document_bien_forme_en_recuperation() {
+        let decision = recuperer(
+            "<Document><Donnees_Techniques><Identification>DTA_1</Identification></Donnees_Techniques></Document>",
+        );
+        assert_eq!(decision.qualite_parsing, Some(QualiteParsing::Complete));
+        assert_eq!(lire("", "").qualite_parsing, None);
+    }
+
+    #[test]
+    fn ignore_les_champs_vides() {
+        let decision = lire("<Solution>  </Solution><Solution/>", "");
+        assert_eq!(champ(&decision, "solution"), None);
+        assert_eq!(champ(&decision, "texte_integral"), None);
+    }
+}
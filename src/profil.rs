@@ -0,0 +1,196 @@
+use crate::configuration::FormatProfil;
+use crate::modele::Decision;
+use crate::qualite::JURIDICTION_INCONNUE;
+
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Champs profilés sans `--champ`, s'ils figurent dans la table des champs.
+pub const CHAMPS_PROFIL_DEFAUT: [&str; 4] = [
+    "solution",
+    "type_recours",
+    "formation_jugement",
+    "type_decision",
+];
+
+#[derive(Default)]
+struct Occurrences {
+    nombre: usize,
+    /// Plus petits identifiants, pour un résultat stable d'un run à l'autre.
+    exemples: Vec<String>,
+}
+
+/// Une ligne du profil : une valeur d'un champ dans une juridiction.
+#[derive(Serialize)]
+pub struct LigneProfil {
+    pub champ: String,
+    pub juridiction: String,
+    pub valeur: String,
+    pub occurrences: usize,
+    pub exemples: Vec<String>,
+}
+
+/// Valeurs des champs telles qu'elles sortent de la table des champs, avant
+/// tout regroupement : la base pour régler les normalisations.
+pub struct Profil {
+    champs: Vec<String>,
+    exemples: usize,
+    /// (champ, juridiction, valeur).
+    valeurs: DashMap<(usize, String, String), Occurrences>,
+}
+
+impl Profil {
+    pub fn new(champs: Vec<String>, exemples: usize) -> Self {
+        Profil {
+            champs,
+            exemples,
+            valeurs: DashMap::new(),
+        }
+    }
+
+    pub fn ajouter(&self, decision: &Decision) {
+        let juridiction = decision
+            .juridiction
+            .as_deref()
+            .unwrap_or(JURIDICTION_INCONNUE);
+        for (indice, champ) in self.champs.iter().enumerate() {
+            let valeurs: Vec<Cow<str>> = match decision.champs.get(champ) {
+                Some(Value::String(valeur)) => vec![Cow::Borrowed(valeur)],
+                Some(Value::Bool(valeur)) => vec![Cow::Owned(valeur.to_string())],
+                Some(Value::Array(valeurs)) => valeurs
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(Cow::Borrowed)
+                    .collect(),
+                _ => continue,
+            };
+            for valeur in valeurs {
+                let mut occurrences = self
+                    .valeurs
+                    .entry((indice, juridiction.to_string(), valeur.to_string()))
+                    .or_default();
+                occurrences.nombre += 1;
+                if self.exemples > 0 {
+                    let position = occurrences
+                        .exemples
+                        .binary_search(&decision.id)
+                        .unwrap_or_else(|position| position);
+                    if position < self.exemples {
+                        occurrences.exemples.insert(position, decision.id.clone());
+                        occurrences.exemples.truncate(self.exemples);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Par champ (ordre demandé), juridiction, puis du plus fréquent au plus
+    /// rare.
+    pub fn lignes(self) -> Vec<LigneProfil> {
+        let mut lignes: Vec<(usize, LigneProfil)> = self
+            .valeurs
+            .into_iter()
+            .map(|((indice, juridiction, valeur), occurrences)| {
+                (
+                    indice,
+                    LigneProfil {
+                        champ: self.champs[indice].clone(),
+                        juridiction,
+                        valeur,
+                        occurrences: occurrences.nombre,
+                        exemples: occurrences.exemples,
+                    },
+                )
+            })
+            .collect();
+        lignes.sort_by(|(a, la), (b, lb)| {
+            a.cmp(b)
+                .then_with(|| la.juridiction.cmp(&lb.juridiction))
+                .then_with(|| lb.occurrences.cmp(&la.occurrences))
+                .then_with(|| la.valeur.cmp(&lb.valeur))
+        });
+        lignes.into_iter().map(|(_, ligne)| ligne).collect()
+    }
+}
+
+pub fn ecrire_profil(
+    lignes: &[LigneProfil],
+    format: FormatProfil,
+    sortie: &mut impl Write,
+) -> io::Result<()> {
+    match format {
+        FormatProfil::Json => {
+            serde_json::to_writer_pretty(&mut *sortie, lignes)?;
+            writeln!(sortie)
+        }
+        FormatProfil::Csv => {
+            writeln!(sortie, "champ,juridiction,valeur,occurrences,exemples")?;
+            for ligne in lignes {
+                writeln!(
+                    sortie,
+                    "{},{},{},{},{}",
+                    cellule_csv(&ligne.champ),
+                    cellule_csv(&ligne.juridiction),
+                    cellule_csv(&ligne.valeur),
+                    ligne.occurrences,
+                    cellule_csv(&ligne.exemples.join(" "))
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Entre guillemets si la valeur contient une virgule, un guillemet ou un
+/// saut de ligne, les guillemets étant doublés.
+pub fn cellule_csv(valeur: &str) -> Cow<'_, str> {
+    if valeur.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", valeur.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(valeur)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Map;
+
+    fn decision(id: &str, juridiction: &str, solution: &str) -> Decision {
+        let mut champs = Map::new();
+        champs.insert("solution".to_string(), Value::String(solution.to_string()));
+        Decision {
+            id: id.to_string(),
+            juridiction: Some(juridiction.to_string()),
+            champs,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compte_les_valeurs_par_juridiction_avec_les_plus_petits_identifiants() {
+        let profil = Profil::new(vec!["solution".to_string()], 2);
+        for (id, juridiction, solution) in [
+            ("DTA_3", "TA", "Rejet"),
+            ("DTA_1", "TA", "Rejet"),
+            ("DTA_2", "TA", "Rejet"),
+            ("DTA_4", "TA", "Non-lieu, désistement"),
+            ("CE_1", "CE", "Rejet"),
+        ] {
+            profil.ajouter(&decision(id, juridiction, solution));
+        }
+        let mut csv = Vec::new();
+        ecrire_profil(&profil.lignes(), FormatProfil::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "champ,juridiction,valeur,occurrences,exemples\n\
+             solution,CE,Rejet,1,CE_1\n\
+             solution,TA,Rejet,3,DTA_1 DTA_2\n\
+             solution,TA,\"Non-lieu, désistement\",1,DTA_4\n"
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use crate::champs::{TypeChamp, champs};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Au-delà, les valeurs d'un champ ne sont plus dénombrées (numéros de
+/// dossier, ECLI...) : `distincts` devient un minimum.
+const MAX_VALEURS_DISTINCTES: usize = 100_000;
+
+/// Valeurs les plus fréquentes conservées dans le rapport.
+const VALEURS_FREQUENTES: usize = 10;
+
+/// Juridiction des décisions dont ni le code ni l'identifiant ne la donnent.
+pub const JURIDICTION_INCONNUE: &str = "inconnue";
+
+/// État d'un champ dans une décision, du pire au meilleur.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EtatChamp {
+    /// Balise absente du document.
+    Manquant,
+    /// Balise présente mais sans texte (ou sans texte une fois le HTML retiré).
+    Vide,
+    /// Date écartée par `normaliser_date`.
+    DateInvalide,
+    Present,
+}
+
+#[derive(Default)]
+struct Statistiques {
+    presents: usize,
+    manquants: usize,
+    vides: usize,
+    dates_invalides: usize,
+    valeurs: HashMap<String, usize>,
+    plafonne: bool,
+}
+
+impl Statistiques {
+    fn compter_valeur(&mut self, valeur: &str, nombre: usize) {
+        if let Some(occurrences) = self.valeurs.get_mut(valeur) {
+            *occurrences += nombre;
+        } else if self.valeurs.len() < MAX_VALEURS_DISTINCTES {
+            self.valeurs.insert(valeur.to_string(), nombre);
+        } else {
+            self.plafonne = true;
+        }
+    }
+
+    fn fusionner(&mut self, autre: &Statistiques) {
+        self.presents += autre.presents;
+        self.manquants += autre.manquants;
+        self.vides += autre.vides;
+        self.dates_invalides += autre.dates_invalides;
+        self.plafonne |= autre.plafonne;
+        for (valeur, nombre) in &autre.valeurs {
+            self.compter_valeur(valeur, *nombre);
+        }
+    }
+}
+
+/// Décisions et statistiques par (juridiction, champ) d'un run.
+#[derive(Default)]
+struct Collecte {
+    statistiques: DashMap<(String, String), Statistiques>,
+    decisions: DashMap<String, usize>,
+}
+
+static COLLECTE: LazyLock<Collecte> = LazyLock::new(Collecte::default);
+
+/// Compte une décision analysée et l'état de chacun de ses champs ; `valeur`
+/// est la valeur produite (chaîne, liste ou `null`).
+pub fn enregistrer_decision<'a>(
+    juridiction: Option<&str>,
+    champs_decision: impl IntoIterator<Item = (&'a str, EtatChamp, &'a Value)>,
+) {
+    COLLECTE.enregistrer(juridiction, champs_decision);
+}
+
+impl Collecte {
+    fn enregistrer<'a>(
+        &self,
+        juridiction: Option<&str>,
+        champs_decision: impl IntoIterator<Item = (&'a str, EtatChamp, &'a Value)>,
+    ) {
+        let juridiction = juridiction.unwrap_or(JURIDICTION_INCONNUE);
+        *self.decisions.entry(juridiction.to_string()).or_default() += 1;
+        let table = champs();
+        for (champ, etat, valeur) in champs_decision {
+            let mut statistiques = self
+                .statistiques
+                .entry((juridiction.to_string(), champ.to_string()))
+                .or_default();
+            match etat {
+                EtatChamp::Present => statistiques.presents += 1,
+                EtatChamp::Manquant => statistiques.manquants += 1,
+                EtatChamp::Vide => statistiques.vides += 1,
+                EtatChamp::DateInvalide => statistiques.dates_invalides += 1,
+            }
+            // Le texte intégral n'a pas de valeurs fréquentes.
+            if table.type_champ(champ) == Some(TypeChamp::Texte) {
+                continue;
+            }
+            match valeur {
+                Value::String(valeur) => statistiques.compter_valeur(valeur, 1),
+                Value::Bool(valeur) => statistiques.compter_valeur(&valeur.to_string(), 1),
+                Value::Array(valeurs) => {
+                    for valeur in valeurs.iter().filter_map(Value::as_str) {
+                        statistiques.compter_valeur(valeur, 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ValeurFrequente {
+    pub valeur: String,
+    pub occurrences: usize,
+}
+
+#[derive(Serialize)]
+pub struct BilanChamp {
+    pub champ: String,
+    pub presents: usize,
+    pub manquants: usize,
+    pub vides: usize,
+    pub dates_invalides: usize,
+    /// Absent pour les champs `texte`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distincts: Option<usize>,
+    /// `distincts` est un minimum.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub distincts_plafonnes: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub valeurs_frequentes: Vec<ValeurFrequente>,
+}
+
+impl BilanChamp {
+    fn depuis(champ: &str, statistiques: &Statistiques) -> Self {
+        let mut frequentes: Vec<(&String, &usize)> = statistiques.valeurs.iter().collect();
+        frequentes.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let texte = champs().type_champ(champ) == Some(TypeChamp::Texte);
+        BilanChamp {
+            champ: champ.to_string(),
+            presents: statistiques.presents,
+            manquants: statistiques.manquants,
+            vides: statistiques.vides,
+            dates_invalides: statistiques.dates_invalides,
+            distincts: (!texte).then_some(statistiques.valeurs.len()),
+            distincts_plafonnes: statistiques.plafonne,
+            valeurs_frequentes: frequentes
+                .into_iter()
+                .take(VALEURS_FREQUENTES)
+                .map(|(valeur, occurrences)| ValeurFrequente {
+                    valeur: valeur.clone(),
+                    occurrences: *occurrences,
+                })
+                .collect(),
+        }
+    }
+
+    /// Décisions sans valeur pour le champ, quelle qu'en soit la raison.
+    pub fn absents(&self) -> usize {
+        self.manquants + self.vides + self.dates_invalides
+    }
+}
+
+#[derive(Serialize)]
+pub struct BilanJuridiction {
+    pub juridiction: String,
+    pub decisions: usize,
+    pub champs: Vec<BilanChamp>,
+}
+
+/// Rapport de qualité d'un run, écrit en JSON et en texte.
+#[derive(Serialize)]
+pub struct RapportQualite {
+    pub date: String,
+    pub decisions: usize,
+    /// Tous les champs, toutes juridictions confondues.
+    pub champs: Vec<BilanChamp>,
+    pub juridictions: Vec<BilanJuridiction>,
+}
+
+impl RapportQualite {
+    /// Champs dans l'ordre de la table, juridictions par ordre alphabétique.
+    pub fn construire() -> Self {
+        Self::depuis(&COLLECTE)
+    }
+
+    fn depuis(collecte: &Collecte) -> Self {
+        let noms: Vec<&String> = champs().noms().iter().filter(|nom| *nom != "id").collect();
+        let mut juridictions: Vec<(String, usize)> = collecte
+            .decisions
+            .iter()
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect();
+        juridictions.sort();
+
+        let mut totaux: HashMap<&str, Statistiques> = HashMap::new();
+        let juridictions = juridictions
+            .into_iter()
+            .map(|(juridiction, decisions)| {
+                let champs = noms
+                    .iter()
+                    .map(|nom| {
+                        let cle = (juridiction.clone(), nom.to_string());
+                        let vide = Statistiques::default();
+                        let statistiques = collecte.statistiques.get(&cle);
+                        let statistiques = statistiques.as_deref().unwrap_or(&vide);
+                        totaux
+                            .entry(nom.as_str())
+                            .or_default()
+                            .fusionner(statistiques);
+                        BilanChamp::depuis(nom, statistiques)
+                    })
+                    .collect();
+                BilanJuridiction {
+                    juridiction,
+                    decisions,
+                    champs,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let vide = Statistiques::default();
+        RapportQualite {
+            date: chrono::Local::now().to_rfc3339(),
+            decisions: juridictions.iter().map(|j| j.decisions).sum(),
+            champs: noms
+                .iter()
+                .map(|nom| BilanChamp::depuis(nom, totaux.get(nom.as_str()).unwrap_or(&vide)))
+                .collect(),
+            juridictions,
+        }
+    }
+
+    /// Décisions sans valeur, par champ, pour les seuils `--max-manquants`.
+    pub fn absents(&self) -> HashMap<String, usize> {
+        self.champs
+            .iter()
+            .map(|bilan| (bilan.champ.clone(), bilan.absents()))
+            .collect()
+    }
+
+    /// Écrit `qualite-AAAAMMJJ-HHMMSS.json` et `.txt` dans `dossier`, un
+    /// couple de fichiers par run pour suivre l'évolution des données.
+    pub fn ecrire(&self, dossier: &Path) -> std::io::Result<PathBuf> {
+        fs::create_dir_all(dossier)?;
+        let horodatage = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let json = dossier.join(format!("qualite-{}.json", horodatage));
+        fs::write(&json, serde_json::to_vec_pretty(self)?)?;
+        fs::write(json.with_extension("txt"), self.texte())?;
+        Ok(json)
+    }
+
+    fn texte(&self) -> String {
+        let mut texte = format!(
+            "Rapport de qualité du {}\n{} décisions\n",
+            self.date, self.decisions
+        );
+        ecrire_tableau(
+            &mut texte,
+            "Toutes juridictions",
+            self.decisions,
+            &self.champs,
+        );
+        for juridiction in &self.juridictions {
+            ecrire_tableau(
+                &mut texte,
+                &juridiction.juridiction,
+                juridiction.decisions,
+                &juridiction.champs,
+            );
+        }
+        texte
+    }
+}
+
+fn ecrire_tableau(texte: &mut String, titre: &str, decisions: usize, champs: &[BilanChamp]) {
+    let _ = writeln!(texte, "\n== {} ({} décisions) ==\n", titre, decisions);
+    let _ = writeln!(
+        texte,
+        "{:<24} {:>9} {:>9} {:>9} {:>9} {:>9} {:>8}",
+        "champ", "présents", "manquants", "vides", "dates inv", "distincts", "absents"
+    );
+    for bilan in champs {
+        let distincts = match bilan.distincts {
+            Some(n) if bilan.distincts_plafonnes => format!("≥{}", n),
+            Some(n) => n.to_string(),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(
+            texte,
+            "{:<24} {:>9} {:>9} {:>9} {:>9} {:>9} {:>7.1}%",
+            bilan.champ,
+            bilan.presents,
+            bilan.manquants,
+            bilan.vides,
+            bilan.dates_invalides,
+            distincts,
+            pourcentage(bilan.absents(), decisions)
+        );
+    }
+    for bilan in champs.iter().filter(|b| !b.valeurs_frequentes.is_empty()) {
+        let _ = writeln!(texte, "\n{} :", bilan.champ);
+        for frequente in &bilan.valeurs_frequentes {
+            let _ = writeln!(
+                texte,
+                "  {:>9}  {}",
+                frequente.occurrences, frequente.valeur
+            );
+        }
+    }
+}
+
+pub fn pourcentage(nombre: usize, total: usize) -> f64 {
+    if total > 0 {
+        nombre as f64 / total as f64 * 100.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classe_les_valeurs_frequentes_et_fusionne_les_juridictions() {
+        let mut ta = Statistiques {
+            presents: 3,
+            manquants: 1,
+            ..Default::default()
+        };
+        for valeur in ["Rejet", "Non-lieu", "Rejet"] {
+            ta.compter_valeur(valeur, 1);
+        }
+        let mut caa = Statistiques {
+            presents: 1,
+            dates_invalides: 1,
+            ..Default::default()
+        };
+        caa.compter_valeur("Non-lieu", 1);
+
+        let mut total = Statistiques::default();
+        total.fusionner(&ta);
+        total.fusionner(&caa);
+        let bilan = BilanChamp::depuis("solution", &total);
+
+        assert_eq!(bilan.presents, 4);
+        assert_eq!(bilan.absents(), 2);
+        assert_eq!(bilan.distincts, Some(2));
+        let frequentes: Vec<(&str, usize)> = bilan
+            .valeurs_frequentes
+            .iter()
+            .map(|f| (f.valeur.as_str(), f.occurrences))
+            .collect();
+        // À égalité, ordre alphabétique.
+        assert_eq!(frequentes, [("Non-lieu", 2), ("Rejet", 2)]);
+    }
+
+    /// Deux décisions du TA et une sans juridiction.
+    fn collecte() -> Collecte {
+        let collecte = Collecte::default();
+        let rejet = Value::String("Rejet".to_string());
+        let date = Value::String("2024-01-05".to_string());
+        let texte = Value::String("Le tribunal...".to_string());
+        collecte.enregistrer(
+            Some("TA"),
+            [
+                ("solution", EtatChamp::Present, &rejet),
+                ("date_lecture", EtatChamp::Manquant, &Value::Null),
+                ("texte_integral", EtatChamp::Present, &texte),
+            ],
+        );
+        collecte.enregistrer(
+            Some("TA"),
+            [
+                ("solution", EtatChamp::Present, &rejet),
+                ("date_lecture", EtatChamp::DateInvalide, &Value::Null),
+                ("texte_integral", EtatChamp::Present, &texte),
+            ],
+        );
+        collecte.enregistrer(
+            None,
+            [
+                ("solution", EtatChamp::Vide, &Value::Null),
+                ("date_lecture", EtatChamp::Present, &date),
+                ("texte_integral", EtatChamp::Manquant, &Value::Null),
+            ],
+        );
+        collecte
+    }
+
+    fn bilan<'a>(champs: &'a [BilanChamp], nom: &str) -> &'a BilanChamp {
+        champs.iter().find(|bilan| bilan.champ == nom).unwrap()
+    }
+
+    #[test]
+    fn regroupe_les_decisions_par_juridiction() {
+        let rapport = RapportQualite::depuis(&collecte());
+        assert_eq!(rapport.decisions, 3);
+        let juridictions: Vec<(&str, usize)> = rapport
+            .juridictions
+            .iter()
+            .map(|j| (j.juridiction.as_str(), j.decisions))
+            .collect();
+        assert_eq!(juridictions, [("TA", 2), (JURIDICTION_INCONNUE, 1)]);
+
+        let ta = &rapport.juridictions[0].champs;
+        assert_eq!(bilan(ta, "solution").presents, 2);
+        assert_eq!(bilan(ta, "solution").distincts, Some(1));
+        assert_eq!(bilan(ta, "date_lecture").manquants, 1);
+        assert_eq!(bilan(ta, "date_lecture").dates_invalides, 1);
+        let inconnue = &rapport.juridictions[1].champs;
+        assert_eq!(bilan(inconnue, "solution").vides, 1);
+        assert_eq!(bilan(inconnue, "date_lecture").presents, 1);
+
+        // Champs de la table sans l'id, totaux de toutes les juridictions.
+        assert_eq!(rapport.champs[0].champ, "date_mise_jour");
+        assert_eq!(bilan(&rapport.champs, "date_mise_jour").presents, 0);
+        assert_eq!(bilan(&rapport.champs, "solution").presents, 2);
+        assert_eq!(bilan(&rapport.champs, "texte_integral").distincts, None);
+        let absents = rapport.absents();
+        assert_eq!(absents["solution"], 1);
+        assert_eq!(absents["date_lecture"], 2);
+        assert_eq!(absents["texte_integral"], 1);
+    }
+
+    #[test]
+    fn ecrit_le_rapport_en_json_et_en_texte() {
+        let dossier =
+            std::env::temp_dir().join(format!("xml_to_elastic_{}_qualite", std::process::id()));
+        let _ = fs::remove_dir_all(&dossier);
+        let json = RapportQualite::depuis(&collecte())
+            .ecrire(&dossier)
+            .unwrap();
+
+        let nom = json.file_name().unwrap().to_str().unwrap();
+        let horodatage = nom
+            .strip_prefix("qualite-")
+            .and_then(|nom| nom.strip_suffix(".json"))
+            .unwrap();
+        assert_eq!(horodatage.len(), "AAAAMMJJ-HHMMSS".len(), "{}", nom);
+        assert_eq!(horodatage.as_bytes()[8], b'-', "{}", nom);
+
+        let rapport: Value = serde_json::from_slice(&fs::read(&json).unwrap()).unwrap();
+        assert_eq!(rapport["decisions"], 3);
+        assert_eq!(rapport["juridictions"][0]["juridiction"], "TA");
+        assert_eq!(
+            rapport["juridictions"][1]["juridiction"],
+            JURIDICTION_INCONNUE
+        );
+        let champ = |nom: &str| {
+            rapport["champs"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|champ| champ["champ"] == nom)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            champ("solution")["valeurs_frequentes"],
+            serde_json::json!([{ "valeur": "Rejet", "occurrences": 2 }])
+        );
+        assert!(champ("texte_integral").get("distincts").is_none());
+
+        let texte = fs::read_to_string(json.with_extension("txt")).unwrap();
+        assert!(texte.contains("== Toutes juridictions (3 décisions) =="));
+        assert!(texte.contains("== inconnue (1 décisions) =="));
+        fs::remove_dir_all(&dossier).unwrap();
+    }
+
+    #[test]
+    fn ne_denombre_pas_le_texte_integral() {
+        let mut statistiques = Statistiques::default();
+        statistiques.compter_valeur("Le tribunal...", 1);
+        let bilan = BilanChamp::depuis("texte_integral", &statistiques);
+        assert_eq!(bilan.distincts, None);
+    }
+}
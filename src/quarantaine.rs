@@ -0,0 +1,248 @@
+use crate::parser::ErreurLecture;
+
+use owo_colors::OwoColorize;
+use serde_json::json;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Copie des fichiers rejetés dans une arborescence reproduisant les chemins
+/// absolus des sources, chacun accompagné de `<fichier>.erreur.json`.
+pub struct Quarantaine {
+    dossier: PathBuf,
+    /// Lien physique plutôt que copie, avec repli sur la copie entre deux
+    /// systèmes de fichiers.
+    lien: bool,
+    fichiers: AtomicUsize,
+}
+
+impl Quarantaine {
+    pub fn new(dossier: &Path, lien: bool) -> Self {
+        Quarantaine {
+            dossier: dossier.to_path_buf(),
+            lien,
+            fichiers: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn dossier(&self) -> &Path {
+        &self.dossier
+    }
+
+    pub fn nombre(&self) -> usize {
+        self.fichiers.load(Ordering::Relaxed)
+    }
+
+    /// `contenu` est fourni pour les entrées d'archive, qui n'existent pas
+    /// sur le disque.
+    pub fn ajouter(&self, source: &Path, contenu: Option<&[u8]>, erreur: &ErreurLecture) {
+        if let Err(e) = self.placer(source, contenu, erreur) {
+            eprintln!(
+                "{} {} {}",
+                "Erreur quarantaine:".red().bold(),
+                source.display(),
+                e.to_string().red()
+            );
+        }
+    }
+
+    fn placer(
+        &self,
+        source: &Path,
+        contenu: Option<&[u8]>,
+        erreur: &ErreurLecture,
+    ) -> io::Result<()> {
+        let cible = self.dossier.join(chemin_relatif(source));
+        if let Some(parent) = cible.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let destination = self.deposer(source, contenu, &cible)?;
+        let mut annexe = destination.into_os_string();
+        annexe.push(".erreur.json");
+        let description = json!({
+            "fichier": source.to_string_lossy(),
+            "categorie": erreur.categorie(),
+            "erreur": erreur.to_string(),
+            "position": erreur.position(),
+            "date": chrono::Local::now().to_rfc3339(),
+        });
+        fs::write(annexe, serde_json::to_vec_pretty(&description)?)?;
+        self.fichiers.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Dépose le fichier sous `cible`, ou `x.2.xml`, `x.3.xml`... si un
+    /// fichier de ce nom s'y trouve déjà, d'un run précédent par exemple :
+    /// rien n'est écrasé.
+    fn deposer(&self, source: &Path, contenu: Option<&[u8]>, cible: &Path) -> io::Result<PathBuf> {
+        let mut numero = 1;
+        loop {
+            let destination = numeroter(cible, numero);
+            match self.deposer_sous(source, contenu, &destination) {
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => numero += 1,
+                resultat => return resultat.map(|()| destination),
+            }
+        }
+    }
+
+    fn deposer_sous(
+        &self,
+        source: &Path,
+        contenu: Option<&[u8]>,
+        destination: &Path,
+    ) -> io::Result<()> {
+        if contenu.is_none() && self.lien {
+            match fs::hard_link(source, destination) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+                Err(_) => {}
+            }
+        }
+        let mut fichier = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(destination)?;
+        let resultat = match contenu {
+            Some(contenu) => fichier.write_all(contenu),
+            None => File::open(source).and_then(|mut f| io::copy(&mut f, &mut fichier).map(drop)),
+        };
+        if resultat.is_err() {
+            let _ = fs::remove_file(destination);
+        }
+        resultat
+    }
+}
+
+/// `x.xml` devient `x.2.xml` pour `numero` 2.
+fn numeroter(cible: &Path, numero: usize) -> PathBuf {
+    if numero == 1 {
+        return cible.to_path_buf();
+    }
+    let nom = cible.file_name().unwrap_or_default().to_string_lossy();
+    let nom = match nom.split_once('.') {
+        Some((base, extension)) => format!("{}.{}.{}", base, numero, extension),
+        None => format!("{}.{}", nom, numero),
+    };
+    cible.with_file_name(nom)
+}
+
+/// Depuis `/data`, `TA/2024/x.xml` et `../data/TA/2024/x.xml` deviennent
+/// tous deux `data/TA/2024/x.xml` : le chemin est rendu absolu, les `..`
+/// résolus et la racine retirée, si bien que deux sources distinctes ne
+/// partagent jamais une destination.
+fn chemin_relatif(source: &Path) -> PathBuf {
+    let absolu = std::path::absolute(source).unwrap_or_else(|_| source.to_path_buf());
+    let mut relatif = PathBuf::new();
+    for composant in absolu.components() {
+        match composant {
+            // Lecteur Windows : `C:` devient `C`.
+            Component::Prefix(prefixe) => {
+                relatif.push(prefixe.as_os_str().to_string_lossy().replace(':', ""))
+            }
+            Component::Normal(nom) => relatif.push(nom),
+            Component::ParentDir => {
+                relatif.pop();
+            }
+            Component::RootDir | Component::CurDir => {}
+        }
+    }
+    relatif
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dossier(nom: &str) -> PathBuf {
+        let dossier =
+            std::env::temp_dir().join(format!("xml_to_elastic_{}_{}", std::process::id(), nom));
+        let _ = fs::remove_dir_all(&dossier);
+        dossier
+    }
+
+    fn erreur() -> ErreurLecture {
+        ErreurLecture::XmlMalforme {
+            position: 64,
+            message: "balise non fermée".to_string(),
+        }
+    }
+
+    #[test]
+    fn reproduit_le_chemin_absolu_de_la_source() {
+        let courant = std::env::current_dir().unwrap();
+        let relatif = chemin_relatif(Path::new("TA/x.xml"));
+        assert!(relatif.is_relative());
+        assert!(relatif.ends_with("TA/x.xml"));
+        assert_eq!(chemin_relatif(&courant.join("TA/x.xml")), relatif);
+        assert_eq!(chemin_relatif(Path::new("TA/../TA/./x.xml")), relatif);
+        assert_eq!(
+            chemin_relatif(Path::new("/a/TA/x.xml")),
+            Path::new("a/TA/x.xml")
+        );
+        assert_eq!(
+            chemin_relatif(Path::new("/b/a/TA/x.xml")),
+            Path::new("b/a/TA/x.xml")
+        );
+    }
+
+    #[test]
+    fn copie_le_fichier_et_decrit_l_erreur() {
+        let racine = dossier("quarantaine_copie");
+        let source = racine.join("sources/TA/x.xml");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "<Document>").unwrap();
+        let quarantaine = Quarantaine::new(&racine.join("q"), false);
+        quarantaine.ajouter(&source, None, &erreur());
+
+        let destination = racine.join("q").join(chemin_relatif(&source));
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "<Document>");
+        let annexe = fs::read(destination.with_file_name("x.xml.erreur.json")).unwrap();
+        let annexe: serde_json::Value = serde_json::from_slice(&annexe).unwrap();
+        assert_eq!(annexe["fichier"], source.to_string_lossy().as_ref());
+        assert_eq!(annexe["categorie"], "xml_malforme");
+        assert_eq!(annexe["erreur"], erreur().to_string());
+        assert_eq!(annexe["position"], 64);
+        assert!(annexe["date"].is_string());
+        assert_eq!(quarantaine.nombre(), 1);
+        fs::remove_dir_all(&racine).unwrap();
+    }
+
+    #[test]
+    fn ecrit_le_contenu_d_une_entree_d_archive() {
+        let racine = dossier("quarantaine_archive");
+        let quarantaine = Quarantaine::new(&racine, true);
+        let source = Path::new("/donnees/JADE.tar.gz/JADE/x.xml");
+        quarantaine.ajouter(source, Some(b"<Document>"), &erreur());
+        let destination = racine.join("donnees/JADE.tar.gz/JADE/x.xml");
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "<Document>");
+        assert!(
+            racine
+                .join("donnees/JADE.tar.gz/JADE/x.xml.erreur.json")
+                .exists()
+        );
+        fs::remove_dir_all(&racine).unwrap();
+    }
+
+    #[test]
+    fn n_ecrase_pas_un_fichier_deja_en_quarantaine() {
+        let racine = dossier("quarantaine_doublon");
+        let quarantaine = Quarantaine::new(&racine, false);
+        let source = Path::new("/donnees/TA/x.xml");
+        quarantaine.ajouter(source, Some(b"premier"), &erreur());
+        quarantaine.ajouter(source, Some(b"second"), &erreur());
+        let dossier_ta = racine.join("donnees/TA");
+        assert_eq!(
+            fs::read_to_string(dossier_ta.join("x.xml")).unwrap(),
+            "premier"
+        );
+        assert_eq!(
+            fs::read_to_string(dossier_ta.join("x.2.xml")).unwrap(),
+            "second"
+        );
+        assert!(dossier_ta.join("x.2.xml.erreur.json").exists());
+        assert_eq!(quarantaine.nombre(), 2);
+        fs::remove_dir_all(&racine).unwrap();
+    }
+}
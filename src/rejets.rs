@@ -0,0 +1,169 @@
+use crate::parser::ErreurLecture;
+use crate::quarantaine::Quarantaine;
+
+use dashmap::DashMap;
+use serde_json::json;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Fichiers n'ayant produit aucune décision, une ligne NDJSON par fichier :
+/// `{"fichier","categorie","erreur","position"}`. Le fichier n'est créé qu'au
+/// premier rejet.
+pub struct RapportRejets {
+    chemin: PathBuf,
+    fichier: Mutex<Option<BufWriter<File>>>,
+    compteurs: DashMap<&'static str, usize>,
+    quarantaine: Option<Quarantaine>,
+}
+
+impl RapportRejets {
+    pub fn new(chemin: &Path, quarantaine: Option<Quarantaine>) -> Self {
+        RapportRejets {
+            chemin: chemin.to_path_buf(),
+            fichier: Mutex::new(None),
+            compteurs: DashMap::new(),
+            quarantaine,
+        }
+    }
+
+    pub fn chemin(&self) -> &Path {
+        &self.chemin
+    }
+
+    pub fn quarantaine(&self) -> Option<&Quarantaine> {
+        self.quarantaine.as_ref()
+    }
+
+    /// `contenu` : contenu d'une entrée d'archive, copié en quarantaine. Une
+    /// erreur d'écriture du rapport doit arrêter le run : le rapport ne
+    /// serait plus complet.
+    pub fn enregistrer(
+        &self,
+        source: &Path,
+        contenu: Option<&[u8]>,
+        erreur: &ErreurLecture,
+    ) -> io::Result<()> {
+        *self.compteurs.entry(erreur.categorie()).or_default() += 1;
+        if let Some(quarantaine) = &self.quarantaine {
+            quarantaine.ajouter(source, contenu, erreur);
+        }
+        let ligne = json!({
+            "fichier": source.to_string_lossy(),
+            "categorie": erreur.categorie(),
+            "erreur": erreur.to_string(),
+            "position": erreur.position(),
+        });
+        let mut fichier = self.fichier.lock().unwrap();
+        if fichier.is_none() {
+            *fichier = Some(BufWriter::new(File::create(&self.chemin)?));
+        }
+        match fichier.as_mut() {
+            Some(f) => writeln!(f, "{}", ligne),
+            None => Ok(()),
+        }
+    }
+
+    /// Nombre de rejets par catégorie, du plus fréquent au plus rare.
+    pub fn compteurs(&self) -> Vec<(&'static str, usize)> {
+        let mut compteurs: Vec<(&'static str, usize)> = self
+            .compteurs
+            .iter()
+            .map(|e| (*e.key(), *e.value()))
+            .collect();
+        compteurs.sort_by_key(|c| std::cmp::Reverse(c.1));
+        compteurs
+    }
+
+    pub fn total(&self) -> usize {
+        self.compteurs.iter().map(|e| *e.value()).sum()
+    }
+
+    pub fn terminer(&self) -> io::Result<()> {
+        match self.fichier.lock().unwrap().as_mut() {
+            Some(f) => f.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value;
+
+    fn chemin_temporaire(nom: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xml_to_elastic_{}_{}", std::process::id(), nom))
+    }
+
+    #[test]
+    fn consigne_chaque_rejet_sur_une_ligne() {
+        let chemin = chemin_temporaire("rejets.ndjson");
+        let _ = std::fs::remove_file(&chemin);
+        let rejets = RapportRejets::new(&chemin, None);
+        rejets.terminer().unwrap();
+        assert!(!chemin.exists());
+
+        let malforme = ErreurLecture::XmlMalforme {
+            position: 64,
+            message: "balise non fermée".to_string(),
+        };
+        rejets
+            .enregistrer(Path::new("TA/a.xml"), None, &malforme)
+            .unwrap();
+        assert!(chemin.exists());
+        rejets
+            .enregistrer(
+                Path::new("TA/b.xml"),
+                None,
+                &ErreurLecture::IdentifiantManquant,
+            )
+            .unwrap();
+        rejets
+            .enregistrer(Path::new("TA/c.xml"), Some(b"<"), &malforme)
+            .unwrap();
+        rejets.terminer().unwrap();
+
+        let contenu = std::fs::read_to_string(&chemin).unwrap();
+        std::fs::remove_file(&chemin).unwrap();
+        let lignes: Vec<Value> = contenu
+            .lines()
+            .map(|ligne| serde_json::from_str(ligne).unwrap())
+            .collect();
+        assert_eq!(lignes.len(), 3);
+        assert_eq!(
+            lignes[0],
+            json!({
+                "fichier": "TA/a.xml",
+                "categorie": "xml_malforme",
+                "erreur": malforme.to_string(),
+                "position": 64,
+            })
+        );
+        assert_eq!(lignes[1]["categorie"], "identifiant_manquant");
+        assert_eq!(lignes[1]["position"], Value::Null);
+        assert_eq!(
+            rejets.compteurs(),
+            [("xml_malforme", 2), ("identifiant_manquant", 1)]
+        );
+        assert_eq!(rejets.total(), 3);
+    }
+
+    #[test]
+    fn signale_un_rapport_impossible_a_creer() {
+        let chemin = chemin_temporaire("absent").join("rejets.ndjson");
+        let rejets = RapportRejets::new(&chemin, None);
+        assert!(
+            rejets
+                .enregistrer(
+                    Path::new("a.xml"),
+                    None,
+                    &ErreurLecture::IdentifiantManquant
+                )
+                .is_err()
+        );
+    }
+}
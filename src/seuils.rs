@@ -0,0 +1,127 @@
+use crate::configuration::ConfigurationSeuils;
+use crate::qualite::pourcentage;
+
+use std::collections::HashMap;
+
+/// Bilan du run confronté aux seuils.
+pub struct BilanRun<'a> {
+    pub decisions: usize,
+    pub fichiers_rejetes: usize,
+    pub documents_refuses: usize,
+    pub manquants: &'a HashMap<String, usize>,
+}
+
+/// Règles non respectées par le run, une phrase par règle.
+pub fn verifier(seuils: &ConfigurationSeuils, bilan: &BilanRun) -> Vec<String> {
+    let mut depassements = Vec::new();
+    if seuils.strict && bilan.fichiers_rejetes > 0 {
+        depassements.push(format!(
+            "mode strict: {} fichiers rejetés",
+            bilan.fichiers_rejetes
+        ));
+    }
+    if seuils.strict && bilan.documents_refuses > 0 {
+        depassements.push(format!(
+            "mode strict: {} documents refusés par Elasticsearch",
+            bilan.documents_refuses
+        ));
+    }
+    if let Some(max) = seuils.max_rejets
+        && bilan.fichiers_rejetes > max
+    {
+        depassements.push(format!(
+            "{} fichiers rejetés pour {} au plus",
+            bilan.fichiers_rejetes, max
+        ));
+    }
+    for (champ, max) in &seuils.max_manquants {
+        let manquants = bilan.manquants.get(champ).copied().unwrap_or(0);
+        let taux = pourcentage(manquants, bilan.decisions);
+        if taux > *max {
+            depassements.push(format!(
+                "{} absent de {:.1}% des décisions pour {}% au plus",
+                champ, taux, max
+            ));
+        }
+    }
+    depassements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bilan(fichiers_rejetes: usize, manquants: &HashMap<String, usize>) -> BilanRun<'_> {
+        BilanRun {
+            decisions: 200,
+            fichiers_rejetes,
+            documents_refuses: 0,
+            manquants,
+        }
+    }
+
+    #[test]
+    fn le_mode_strict_refuse_le_moindre_rejet() {
+        let seuils = ConfigurationSeuils {
+            strict: true,
+            ..Default::default()
+        };
+        let manquants = HashMap::new();
+        assert!(verifier(&seuils, &bilan(0, &manquants)).is_empty());
+        assert_eq!(
+            verifier(&seuils, &bilan(1, &manquants)),
+            ["mode strict: 1 fichiers rejetés"]
+        );
+        let refuses = BilanRun {
+            documents_refuses: 2,
+            ..bilan(0, &manquants)
+        };
+        assert_eq!(
+            verifier(&seuils, &refuses),
+            ["mode strict: 2 documents refusés par Elasticsearch"]
+        );
+    }
+
+    #[test]
+    fn echoue_au_dela_du_maximum_de_rejets() {
+        let seuils = ConfigurationSeuils {
+            max_rejets: Some(3),
+            ..Default::default()
+        };
+        let manquants = HashMap::new();
+        assert!(verifier(&seuils, &bilan(3, &manquants)).is_empty());
+        assert_eq!(
+            verifier(&seuils, &bilan(4, &manquants)),
+            ["4 fichiers rejetés pour 3 au plus"]
+        );
+    }
+
+    #[test]
+    fn echoue_au_dela_du_pourcentage_de_manquants() {
+        let seuils = ConfigurationSeuils {
+            max_manquants: vec![("texte_integral".to_string(), 5.0)],
+            ..Default::default()
+        };
+        let manquants = HashMap::from([("texte_integral".to_string(), 10)]);
+        assert!(verifier(&seuils, &bilan(0, &manquants)).is_empty());
+        let manquants = HashMap::from([("texte_integral".to_string(), 11)]);
+        assert_eq!(
+            verifier(&seuils, &bilan(0, &manquants)),
+            ["texte_integral absent de 5.5% des décisions pour 5% au plus"]
+        );
+    }
+
+    #[test]
+    fn ignore_les_manquants_sans_decision() {
+        let seuils = ConfigurationSeuils {
+            max_manquants: vec![("texte_integral".to_string(), 0.0)],
+            ..Default::default()
+        };
+        let manquants = HashMap::new();
+        let vide = BilanRun {
+            decisions: 0,
+            ..bilan(0, &manquants)
+        };
+        assert!(verifier(&seuils, &vide).is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use dashmap::DashMap;
+use regex::Regex;
+use serde::Deserialize;
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, OnceLock};
+
+/// Règles par défaut, intégrées au binaire.
+const SOLUTIONS_DEFAUT: &str = include_str!("../solutions.toml");
+
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Correspondance {
+    #[default]
+    Debut,
+    Contient,
+    Egal,
+    /// Expression régulière, insensible à la casse.
+    Regex,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefinitionRegle {
+    motif: String,
+    #[serde(default)]
+    correspondance: Correspondance,
+    libelle: String,
+    issue: Option<String>,
+    resultat: Option<String>,
+    #[serde(default)]
+    priorite: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FichierSolutions {
+    version: String,
+    serie_identique: Option<String>,
+    #[serde(rename = "regle", default)]
+    regles: Vec<DefinitionRegle>,
+}
+
+pub struct RegleSolution {
+    pub motif: String,
+    pub correspondance: Correspondance,
+    pub libelle: String,
+    /// Premier niveau de la taxonomie : fond, procédure, renvoi...
+    pub issue: Option<String>,
+    /// Second niveau : favorable, défavorable, partiel...
+    pub resultat: Option<String>,
+    pub priorite: i64,
+    regex: Option<Regex>,
+    /// Solutions reconnues par cette règle.
+    correspondances: AtomicUsize,
+}
+
+impl RegleSolution {
+    /// `solution` est déjà en minuscules et sans espaces autour.
+    fn reconnait(&self, solution: &str) -> bool {
+        match self.correspondance {
+            Correspondance::Debut => solution.starts_with(&self.motif),
+            Correspondance::Contient => solution.contains(&self.motif),
+            Correspondance::Egal => solution == self.motif,
+            Correspondance::Regex => self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(solution)),
+        }
+    }
+
+    pub fn correspondances(&self) -> usize {
+        self.correspondances.load(Ordering::Relaxed)
+    }
+}
+
+/// Règles de `solution_normalisee`, de la plus prioritaire à la moins
+/// prioritaire.
+pub struct ReglesSolutions {
+    pub version: String,
+    pub regles: Vec<RegleSolution>,
+    /// Motif signalant une décision rendue en série identique, en minuscules.
+    serie_identique: Option<String>,
+}
+
+impl ReglesSolutions {
+    pub fn depuis_toml(contenu: &str) -> Result<Self, String> {
+        let fichier: FichierSolutions = toml::from_str(contenu).map_err(|e| e.to_string())?;
+        if fichier.version.trim().is_empty() {
+            return Err("la version des règles est obligatoire".to_string());
+        }
+        let mut regles = Vec::with_capacity(fichier.regles.len());
+        for definition in fichier.regles {
+            if definition.motif.is_empty() || definition.libelle.trim().is_empty() {
+                return Err("chaque règle doit avoir un motif et un libellé".to_string());
+            }
+            let regex = match definition.correspondance {
+                Correspondance::Regex => Some(
+                    Regex::new(&format!("(?i){}", definition.motif))
+                        .map_err(|e| format!("motif {}: {}", definition.motif, e))?,
+                ),
+                _ => None,
+            };
+            let motif = match definition.correspondance {
+                Correspondance::Regex => definition.motif,
+                _ => definition.motif.to_lowercase(),
+            };
+            regles.push(RegleSolution {
+                motif,
+                correspondance: definition.correspondance,
+                libelle: definition.libelle,
+                issue: definition.issue.filter(|issue| !issue.trim().is_empty()),
+                resultat: definition
+                    .resultat
+                    .filter(|resultat| !resultat.trim().is_empty()),
+                priorite: definition.priorite,
+                regex,
+                correspondances: AtomicUsize::new(0),
+            });
+        }
+        // Tri stable : l'ordre du fichier départage les priorités égales.
+        regles.sort_by_key(|regle| std::cmp::Reverse(regle.priorite));
+        Ok(ReglesSolutions {
+            version: fichier.version,
+            regles,
+            serie_identique: fichier
+                .serie_identique
+                .map(|motif| motif.trim().to_lowercase())
+                .filter(|motif| !motif.is_empty()),
+        })
+    }
+
+    pub fn charger(chemin: &Path) -> Result<Self, String> {
+        let contenu = std::fs::read_to_string(chemin)
+            .map_err(|e| format!("lecture de {} impossible: {}", chemin.display(), e))?;
+        Self::depuis_toml(&contenu).map_err(|e| format!("{}: {}", chemin.display(), e))
+    }
+
+    pub fn par_defaut() -> Self {
+        Self::depuis_toml(SOLUTIONS_DEFAUT).expect("solutions.toml intégré invalide")
+    }
+
+    /// Rang de la première règle reconnaissant la solution.
+    fn rang(&self, solution: &str) -> Option<usize> {
+        let solution = solution.trim().to_lowercase();
+        self.regles
+            .iter()
+            .position(|regle| regle.reconnait(&solution))
+    }
+
+    pub fn est_serie_identique(&self, solution: &str) -> bool {
+        self.serie_identique
+            .as_ref()
+            .is_some_and(|motif| solution.to_lowercase().contains(motif.as_str()))
+    }
+}
+
+static REGLES: OnceLock<ReglesSolutions> = OnceLock::new();
+
+/// Remplace les règles par défaut ; à appeler avant la première analyse,
+/// sinon les règles déjà utilisées sont conservées et l'appel échoue.
+pub fn installer_regles(regles: ReglesSolutions) -> Result<(), String> {
+    REGLES
+        .set(regles)
+        .map_err(|_| "règles de solutions déjà utilisées, non remplacées".to_string())
+}
+
+pub fn regles() -> &'static ReglesSolutions {
+    REGLES.get_or_init(ReglesSolutions::par_defaut)
+}
+
+/// Solutions brutes qu'aucune règle ne reconnaît, avec leur nombre
+/// d'occurrences.
+pub static NON_RECONNUES: LazyLock<DashMap<String, usize>> = LazyLock::new(DashMap::new);
+
+/// Rang de la règle reconnaissant chaque solution déjà rencontrée :
+/// `solution_normalisee`, `issue` et `resultat` dérivent d'une seule
+/// recherche parmi les règles.
+static CLASSEMENTS: LazyLock<DashMap<String, Option<usize>>> = LazyLock::new(DashMap::new);
+
+fn regle_solution(solution: &str) -> Option<&'static RegleSolution> {
+    let regles = regles();
+    let connu = CLASSEMENTS.get(solution).map(|rang| *rang);
+    let rang = connu.unwrap_or_else(|| {
+        let rang = regles.rang(solution);
+        CLASSEMENTS.insert(solution.to_string(), rang);
+        rang
+    });
+    rang.map(|rang| &regles.regles[rang])
+}
+
+/// Libellé de la première règle reconnaissant la solution, sinon la solution
+/// telle quelle.
+pub fn normaliser_solution(solution: &str) -> String {
+    match regle_solution(solution) {
+        Some(regle) => {
+            regle.correspondances.fetch_add(1, Ordering::Relaxed);
+            regle.libelle.clone()
+        }
+        None => {
+            *NON_RECONNUES.entry(solution.to_string()).or_default() += 1;
+            solution.to_string()
+        }
+    }
+}
+
+/// Issue de la première règle reconnaissant la solution.
+pub fn issue_solution(solution: &str) -> Option<String> {
+    regle_solution(solution).and_then(|regle| regle.issue.clone())
+}
+
+/// Résultat de la première règle reconnaissant la solution.
+pub fn resultat_solution(solution: &str) -> Option<String> {
+    regle_solution(solution).and_then(|regle| regle.resultat.clone())
+}
+
+/// Solutions non reconnues, de la plus fréquente à la plus rare.
+pub fn non_reconnues() -> Vec<(String, usize)> {
+    let mut non_reconnues: Vec<(String, usize)> = NON_RECONNUES
+        .iter()
+        .map(|e| (e.key().clone(), *e.value()))
+        .collect();
+    non_reconnues.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    non_reconnues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regle<'a>(regles: &'a ReglesSolutions, solution: &str) -> Option<&'a RegleSolution> {
+        regles.rang(solution).map(|rang| &regles.regles[rang])
+    }
+
+    fn libelle<'a>(regles: &'a ReglesSolutions, solution: &str) -> Option<&'a str> {
+        regle(regles, solution).map(|regle| regle.libelle.as_str())
+    }
+
+    #[test]
+    fn les_regles_integrees_reprennent_les_libelles_historiques() {
+        let regles = ReglesSolutions::par_defaut();
+        for (solution, attendu) in [
+            ("Rejet", "Rejet"),
+            ("  REJET au fond ", "Rejet"),
+            ("Série identique - Rejet", "Rejet"),
+            (
+                "Série identique - Satisfaction totale",
+                "Satisfaction totale",
+            ),
+            ("Satisfaction partielle", "Satisfaction partielle"),
+            ("Non-lieu à statuer", "Non-lieu"),
+            ("Expertise", "Mesure d'instruction"),
+            ("Injonction - médiation", "Mesure d'instruction"),
+            ("Supplément d'instruction", "Mesure d'instruction"),
+            ("QPC - Renvoi", "QPC"),
+            ("Autres juridictions - incompétence", "Autre juridiction"),
+        ] {
+            assert_eq!(libelle(&regles, solution), Some(attendu), "{}", solution);
+        }
+        assert_eq!(libelle(&regles, "Annulation"), None);
+    }
+
+    #[test]
+    fn classe_les_solutions_dans_la_taxonomie() {
+        let regles = ReglesSolutions::par_defaut();
+        let taxonomie = |solution: &str| {
+            let regle = regle(&regles, solution).unwrap();
+            (regle.issue.as_deref(), regle.resultat.as_deref())
+        };
+        assert_eq!(taxonomie("Rejet"), (Some("fond"), Some("défavorable")));
+        assert_eq!(
+            taxonomie("Série identique - Satisfaction partielle"),
+            (Some("fond"), Some("partiel"))
+        );
+        assert_eq!(taxonomie("Non-lieu"), (Some("procédure"), None));
+        assert_eq!(
+            taxonomie("Renvoi au Conseil d'Etat"),
+            (Some("renvoi"), None)
+        );
+        assert!(regles.est_serie_identique("SÉRIE IDENTIQUE - Rejet"));
+        assert!(!regles.est_serie_identique("Rejet"));
+    }
+
+    #[test]
+    fn applique_les_priorites_puis_l_ordre_du_fichier() {
+        let regles = ReglesSolutions::depuis_toml(
+            r#"
+            version = "test"
+
+            [[regle]]
+            motif = "rejet"
+            libelle = "Rejet"
+
+            [[regle]]
+            motif = "^rejet.*irrecevab"
+            correspondance = "regex"
+            libelle = "Irrecevabilité"
+            priorite = 10
+
+            [[regle]]
+            motif = "rejet"
+            correspondance = "egal"
+            libelle = "Rejet simple"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            libelle(&regles, "Rejet pour IRRECEVABILITÉ"),
+            Some("Irrecevabilité")
+        );
+        assert_eq!(libelle(&regles, "rejet"), Some("Rejet"));
+    }
+
+    #[test]
+    fn refuse_une_regex_invalide_ou_une_version_absente() {
+        assert!(
+            ReglesSolutions::depuis_toml(
+                "version = \"1\"\n[[regle]]\nmotif = \"(\"\ncorrespondance = \"regex\"\nlibelle = \"X\"\n"
+            )
+            .is_err()
+        );
+        assert!(
+            ReglesSolutions::depuis_toml("[[regle]]\nmotif = \"a\"\nlibelle = \"A\"\n").is_err()
+        );
+    }
+
+    #[test]
+    fn derive_libelle_issue_et_resultat_d_une_seule_recherche() {
+        let solution = "Rejet – classement unique";
+        assert_eq!(normaliser_solution(solution), "Rejet");
+        assert_eq!(issue_solution(solution).as_deref(), Some("fond"));
+        assert_eq!(resultat_solution(solution).as_deref(), Some("défavorable"));
+        assert_eq!(
+            CLASSEMENTS.get(solution).map(|rang| *rang),
+            Some(regles().rang(solution))
+        );
+
+        let inconnue = "Solution sans règle – classement unique";
+        assert_eq!(normaliser_solution(inconnue), inconnue);
+        assert_eq!(issue_solution(inconnue), None);
+        assert_eq!(CLASSEMENTS.get(inconnue).map(|rang| *rang), Some(None));
+    }
+
+    #[test]
+    fn refuse_de_remplacer_des_regles_deja_utilisees() {
+        regles();
+        let erreur = installer_regles(ReglesSolutions::par_defaut()).unwrap_err();
+        assert!(erreur.contains("déjà utilisées"), "{}", erreur);
+    }
+}
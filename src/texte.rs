@@ -1,32 +1,24 @@
 use html_escape::decode_html_entities;
-use std::collections::HashSet;
 
 use chrono::NaiveDate;
+use dashmap::DashMap;
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use std::sync::LazyLock;
+
 lazy_static! {
     static ref RE_HTML: Regex = Regex::new(r"<[^>]+>").unwrap();
+    static ref RE_HTML_BLOC: Regex =
+        Regex::new(r"(?i)<br\s*/?>|</(p|div|li|tr|h[1-6])\s*>").unwrap();
     static ref RE_ESPACE: Regex = Regex::new(r"[ \t]+").unwrap();
-    static ref RE_LIGNE: Regex = Regex::new(r"\n+").unwrap();
-    static ref WHITELIST: HashSet<&'static str> = HashSet::from([
-        "Document/Donnees_Techniques/Identification",
-        "Document/Donnees_Techniques/Date_Mise_Jour",
-        "Document/Dossier/Code_Juridiction",
-        "Document/Dossier/Numero_Dossier",
-        "Document/Dossier/Code_Publication",
-        "Document/Dossier/Nom_Juridiction",
-        "Document/Dossier/Type_Decision",
-        "Document/Dossier/Date_Lecture",
-        "Document/Dossier/Solution",
-        "Document/Dossier/Type_Recours",
-        "Document/Dossier/Numero_ECLI",
-        "Document/Dossier/Avocat_Requerant",
-        "Document/Audience/Formation_Jugement",
-        "Document/Audience/Date_Audience",
-        "Document/Audience/Numero_Role",
-        "Document/Decision/Texte_Integral",
-    ]);
+    static ref RE_LIGNE: Regex = Regex::new(r"[ \t]*\n[ \t\n]*").unwrap();
+    /// Sur le texte replié : « 3eme chambre », « 2eme et 7eme chambres
+    /// reunies »...
+    static ref RE_CHAMBRE: Regex =
+        Regex::new(r"(\d+)(?:eme|ere|er|re|e)?\s+(?:(?:et\s+)?\d+(?:eme|ere|er|re|e)?\s+)*chambres?\b").unwrap();
+    static ref RE_SECTION: Regex =
+        Regex::new(r"(\d+)(?:eme|ere|er|re|e)?\s+(?:sous\s+)?section\b").unwrap();
 }
 pub fn normaliser_date(date: &str) -> Option<String> {
     let formats = ["%Y-%m-%d", "%d-%m-%Y", "%d/%m/%Y", "%Y%m%d"];
@@ -35,108 +27,335 @@ pub fn normaliser_date(date: &str) -> Option<String> {
             return Some(d.format("%Y-%m-%d").to_string());
         }
     }
-    eprintln!("Date invalide: {}", date);
     None
 }
 
-pub fn est_chemin_autorise(chemin: &str) -> bool {
-    WHITELIST.contains(chemin)
+pub fn supprimer_balises_html(texte: &str) -> String {
+    let lignes = RE_HTML_BLOC.replace_all(texte, "\n");
+    let sans_tags = RE_HTML.replace_all(&lignes, "");
+    decode_html_entities(&sans_tags).to_string()
 }
-pub fn normaliser_solution(solution: &str) -> String {
-    let s = solution.trim().to_lowercase();
-    // =====================
-    // Issues juridictionnelles principales
-    // =====================
-    if s.starts_with("rejet") || s.contains(" - rejet") {
-        return "Rejet".to_string();
-    }
-    if s.starts_with("satisfaction totale") || s.contains("série identique - satisfaction totale")
-    {
-        return "Satisfaction totale".to_string();
-    }
-    if s.starts_with("satisfaction partielle")
-        || s.contains("série identique - satisfaction partielle")
-    {
-        return "Satisfaction partielle".to_string();
-    }
-    if s.starts_with("désistement") {
-        return "Désistement".to_string();
-    }
-    if s.starts_with("non-lieu") {
-        return "Non-lieu".to_string();
-    }
-    // =====================
-    // Procédure / Instruction
-    // =====================
-    if s.starts_with("expertise") || s.contains("médiation") {
-        return "Mesure d'instruction".to_string();
-    }
-    if s.starts_with("radiation") {
-        return "Radiation".to_string();
-    }
-    if s.starts_with("supplément d'instruction") {
-        return "Mesure d'instruction".to_string();
-    }
-    if s.starts_with("sursis") {
-        return "Sursis".to_string();
-    }
-    if s.starts_with("dessaisissement") {
-        return "Dessaisissement".to_string();
-    }
-    if s.starts_with("transaction") {
-        return "Transaction".to_string();
-    }
-    if s.starts_with("extension") {
-        return "Extension".to_string();
+pub fn normaliser_espaces(texte: &str) -> String {
+    let t = RE_ESPACE.replace_all(texte, " ");
+    let t = RE_LIGNE.replace_all(&t, "\n");
+    t.trim().to_string()
+}
+/// Ramène `Code_Juridiction` (`TA75`, `CAA69`, `CE`...) à `TA`, `CAA` ou `CE`,
+/// en se rabattant sur le préfixe de l'identifiant (`DTA_`, `ORCE_`...).
+pub fn normaliser_juridiction(code_juridiction: Option<&str>, id: &str) -> Option<String> {
+    let depuis_code = code_juridiction.and_then(|code| {
+        let prefixe: String = code
+            .trim()
+            .chars()
+            .take_while(|c| c.is_alphabetic())
+            .collect::<String>()
+            .to_uppercase();
+        match prefixe.as_str() {
+            "TA" | "CAA" | "CE" => Some(prefixe),
+            _ => None,
+        }
+    });
+    depuis_code.or_else(|| {
+        let juridiction = match id.split('_').next().unwrap_or("") {
+            "ORTA" | "DTA" => "TA",
+            "ORCA" | "DCA" => "CAA",
+            "ORCE" | "DCE" => "CE",
+            _ => return None,
+        };
+        Some(juridiction.to_string())
+    })
+}
+
+/// Types de recours reconnus, sous leur forme repliée (`replier`), et leur
+/// libellé normalisé.
+const TYPES_RECOURS: [(&str, &str); 8] = [
+    ("exces de pouvoir", "Excès de pouvoir"),
+    ("recours pour exces de pouvoir", "Excès de pouvoir"),
+    ("rep", "Excès de pouvoir"),
+    ("plein contentieux", "Plein contentieux"),
+    ("pleine juridiction", "Plein contentieux"),
+    ("recours de plein contentieux", "Plein contentieux"),
+    ("cassation", "Cassation"),
+    ("recours en cassation", "Cassation"),
+];
+
+/// Types de recours bruts qui ne figurent pas dans `TYPES_RECOURS`, avec leur
+/// nombre d'occurrences.
+pub static TYPES_RECOURS_NON_RECONNUS: LazyLock<DashMap<String, usize>> =
+    LazyLock::new(DashMap::new);
+
+/// Minuscules sans accents, ponctuation remplacée par des espaces.
+fn replier(texte: &str) -> String {
+    let mut replie = String::with_capacity(texte.len());
+    for c in texte.chars().flat_map(char::to_lowercase) {
+        match c {
+            'à' | 'â' | 'ä' => replie.push('a'),
+            'é' | 'è' | 'ê' | 'ë' => replie.push('e'),
+            'î' | 'ï' => replie.push('i'),
+            'ô' | 'ö' => replie.push('o'),
+            'ù' | 'û' | 'ü' => replie.push('u'),
+            'ç' => replie.push('c'),
+            'œ' => replie.push_str("oe"),
+            c if c.is_alphanumeric() => replie.push(c),
+            _ => replie.push(' '),
+        }
     }
-    // =====================
-    // Renvois
-    // =====================
-    if s.starts_with("renvoi") {
-        return "Renvoi".to_string();
+    replie.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Libellé normalisé du type de recours, sans tenir compte de la casse, des
+/// accents ni de la ponctuation ; sinon le type tel quel, signalé dans
+/// `non_reconnues`.
+pub fn normaliser_type_recours(
+    type_recours: &str,
+    non_reconnues: &mut Vec<NonReconnue>,
+) -> Option<String> {
+    let type_recours = type_recours.trim();
+    if type_recours.is_empty() {
+        return None;
     }
-    // =====================
-    // QPC
-    // =====================
-    if s.starts_with("qpc") {
-        return "QPC".to_string();
+    let replie = replier(type_recours);
+    match TYPES_RECOURS.iter().find(|(forme, _)| *forme == replie) {
+        Some((_, libelle)) => Some(libelle.to_string()),
+        None => {
+            non_reconnues.push(NonReconnue::TypeRecours(type_recours.to_string()));
+            Some(type_recours.to_string())
+        }
     }
-    // =====================
-    // Questions juridiques spécifiques
-    // =====================
-    if s.starts_with("question préjudicielle") {
-        return "Question préjudicielle".to_string();
+}
+
+/// Types de recours non reconnus, du plus fréquent au plus rare.
+pub fn types_recours_non_reconnus() -> Vec<(String, usize)> {
+    let mut non_reconnus: Vec<(String, usize)> = TYPES_RECOURS_NON_RECONNUS
+        .iter()
+        .map(|e| (e.key().clone(), *e.value()))
+        .collect();
+    non_reconnus.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    non_reconnus
+}
+
+/// `Formation_Jugement` décomposée : « 5ème section - 2ème chambre » donne
+/// une chambre, la chambre 2 et la section 5.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FormationJugement {
+    /// chambre, juge unique, référé, plénière ou section.
+    pub type_formation: Option<&'static str>,
+    /// Première chambre citée, pour des chambres réunies.
+    pub chambre: Option<u32>,
+    /// Numéro de la section ou de la sous-section.
+    pub sous_section: Option<u32>,
+}
+
+pub fn analyser_formation_jugement(formation: &str) -> FormationJugement {
+    let replie = replier(formation);
+    let numero = |regex: &Regex| {
+        regex
+            .captures(&replie)
+            .and_then(|captures| captures[1].parse().ok())
+    };
+    let chambre = numero(&RE_CHAMBRE);
+    let sous_section = numero(&RE_SECTION);
+    let type_formation = if replie.contains("refere") {
+        Some("référé")
+    } else if replie.contains("pleniere") || replie.contains("assemblee") {
+        Some("plénière")
+    } else if replie.contains("juge unique")
+        || replie.contains("magistrat")
+        || replie.contains("statuant seul")
+    {
+        Some("juge unique")
+    } else if replie.contains("chambre") {
+        Some("chambre")
+    } else if replie.contains("section") {
+        Some("section")
+    } else {
+        None
+    };
+    FormationJugement {
+        type_formation,
+        chambre,
+        sous_section,
     }
-    if s.starts_with("demande d'avis") {
-        return "Demande d'avis".to_string();
+}
+
+/// Formations de jugement dont le type n'est pas reconnu, avec leur nombre
+/// d'occurrences.
+pub static FORMATIONS_NON_RECONNUES: LazyLock<DashMap<String, usize>> = LazyLock::new(DashMap::new);
+
+/// Formations déjà analysées : le type, la chambre et la sous-section
+/// dérivent d'une seule analyse par valeur.
+static FORMATIONS: LazyLock<DashMap<String, FormationJugement>> = LazyLock::new(DashMap::new);
+
+/// `analyser_formation_jugement`, une seule fois par valeur.
+pub fn formation_jugement(formation: &str) -> FormationJugement {
+    let connue = FORMATIONS.get(formation).map(|analyse| *analyse);
+    connue.unwrap_or_else(|| {
+        let analyse = analyser_formation_jugement(formation);
+        FORMATIONS.insert(formation.to_string(), analyse);
+        analyse
+    })
+}
+
+/// Type de la formation ; une formation non reconnue est signalée dans
+/// `non_reconnues`.
+pub fn type_formation_jugement(
+    formation: &str,
+    non_reconnues: &mut Vec<NonReconnue>,
+) -> Option<String> {
+    let formation = formation.trim();
+    if formation.is_empty() {
+        return None;
     }
-    // =====================
-    // Autres juridictions
-    // =====================
-    if s.starts_with("autres juridictions") {
-        return "Autre juridiction".to_string();
+    let type_formation = formation_jugement(formation).type_formation;
+    if type_formation.is_none() {
+        non_reconnues.push(NonReconnue::Formation(formation.to_string()));
     }
-    // =====================
-    // Cas inconnu : retourner original
-    // =====================
-    solution.to_string()
+    type_formation.map(str::to_string)
 }
-pub fn supprimer_balises_html(texte: &str) -> String {
-    let sans_tags = RE_HTML.replace_all(texte, "");
-    decode_html_entities(&sans_tags).to_string()
+
+/// Formations non reconnues, de la plus fréquente à la plus rare.
+pub fn formations_non_reconnues() -> Vec<(String, usize)> {
+    let mut non_reconnues: Vec<(String, usize)> = FORMATIONS_NON_RECONNUES
+        .iter()
+        .map(|e| (e.key().clone(), *e.value()))
+        .collect();
+    non_reconnues.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    non_reconnues
 }
-pub fn normaliser_espaces(texte: &str) -> String {
-    let t = RE_ESPACE.replace_all(texte, " ");
-    let t = RE_LIGNE.replace_all(&t, "\n");
-    t.trim().to_string()
+
+/// Valeur brute qu'aucune forme connue ne reconnaît, comptée seulement une
+/// fois la décision acceptée.
+pub enum NonReconnue {
+    TypeRecours(String),
+    Formation(String),
 }
-pub fn extraire_nom_index(id: &str) -> String {
-    let prefix = id.split('_').next().unwrap_or("");
-    let juridiction = match prefix {
-        "ORTA" | "DTA" => "ta",
-        "ORCA" | "DCA" => "caa",
-        "ORCE" | "DCE" => "ce",
-        _ => "inconnu",
+
+pub fn enregistrer_non_reconnue(non_reconnue: NonReconnue) {
+    let (compteur, valeur) = match non_reconnue {
+        NonReconnue::TypeRecours(type_recours) => (&TYPES_RECOURS_NON_RECONNUS, type_recours),
+        NonReconnue::Formation(formation) => (&FORMATIONS_NON_RECONNUES, formation),
     };
-    format!("{}_decisions", juridiction)
+    *compteur.entry(valeur).or_default() += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduit_la_juridiction_du_code_puis_de_l_identifiant() {
+        for (code, id, attendu) in [
+            (Some("TA75"), "DTA_1", Some("TA")),
+            (Some(" caa69 "), "DTA_1", Some("CAA")),
+            (Some("CE"), "ORTA_1", Some("CE")),
+            (Some("CAAXX"), "DCA_1", Some("CAA")),
+            (Some("TC"), "DCE_1", Some("CE")),
+            (None, "ORTA_1", Some("TA")),
+            (None, "DTA_1", Some("TA")),
+            (None, "ORCA_1", Some("CAA")),
+            (None, "DCA_1", Some("CAA")),
+            (None, "ORCE_1", Some("CE")),
+            (None, "DCE_1", Some("CE")),
+            (Some(""), "X_1", None),
+            (None, "1234", None),
+        ] {
+            assert_eq!(
+                normaliser_juridiction(code, id).as_deref(),
+                attendu,
+                "{:?} {}",
+                code,
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn normalise_les_variantes_du_type_de_recours() {
+        let mut non_reconnues = Vec::new();
+        for (brut, attendu) in [
+            ("Excès de pouvoir", "Excès de pouvoir"),
+            ("  excès de pouvoir ", "Excès de pouvoir"),
+            ("EXCES DE POUVOIR", "Excès de pouvoir"),
+            ("Recours pour excès de pouvoir", "Excès de pouvoir"),
+            ("Plein contentieux", "Plein contentieux"),
+            ("plein-contentieux", "Plein contentieux"),
+            ("Cassation", "Cassation"),
+        ] {
+            assert_eq!(
+                normaliser_type_recours(brut, &mut non_reconnues).as_deref(),
+                Some(attendu),
+                "{}",
+                brut
+            );
+        }
+        assert_eq!(normaliser_type_recours("  ", &mut non_reconnues), None);
+        assert!(non_reconnues.is_empty());
+        assert_eq!(
+            normaliser_type_recours("Contentieux répressif", &mut non_reconnues).as_deref(),
+            Some("Contentieux répressif")
+        );
+        assert!(matches!(
+            non_reconnues.as_slice(),
+            [NonReconnue::TypeRecours(type_recours)] if type_recours == "Contentieux répressif"
+        ));
+        non_reconnues.into_iter().for_each(enregistrer_non_reconnue);
+        assert!(
+            types_recours_non_reconnus()
+                .iter()
+                .any(|(type_recours, _)| type_recours == "Contentieux répressif")
+        );
+    }
+
+    #[test]
+    fn decompose_la_formation_de_jugement() {
+        let formation = |type_formation, chambre, sous_section| FormationJugement {
+            type_formation: Some(type_formation),
+            chambre,
+            sous_section,
+        };
+        for (brute, attendu) in [
+            ("3ème chambre", formation("chambre", Some(3), None)),
+            ("10EME CHAMBRE", formation("chambre", Some(10), None)),
+            ("1ère Chambre", formation("chambre", Some(1), None)),
+            (
+                "5ème Section - 2ème Chambre",
+                formation("chambre", Some(2), Some(5)),
+            ),
+            (
+                "2ème et 7ème chambres réunies",
+                formation("chambre", Some(2), None),
+            ),
+            (
+                "3ème sous-section jugeant seule",
+                formation("section", None, Some(3)),
+            ),
+            ("Juge des référés", formation("référé", None, None)),
+            ("Plénière", formation("plénière", None, None)),
+            ("Formation plénière", formation("plénière", None, None)),
+            (
+                "magistrat statuant seul",
+                formation("juge unique", None, None),
+            ),
+            ("Section du contentieux", formation("section", None, None)),
+        ] {
+            assert_eq!(analyser_formation_jugement(brute), attendu, "{}", brute);
+            assert_eq!(formation_jugement(brute), attendu, "{}", brute);
+        }
+        let mut non_reconnues = Vec::new();
+        assert_eq!(
+            type_formation_jugement("3ème chambre", &mut non_reconnues).as_deref(),
+            Some("chambre")
+        );
+        assert_eq!(type_formation_jugement("Greffe", &mut non_reconnues), None);
+        assert!(matches!(
+            non_reconnues.as_slice(),
+            [NonReconnue::Formation(formation)] if formation == "Greffe"
+        ));
+        non_reconnues.into_iter().for_each(enregistrer_non_reconnue);
+        assert!(
+            formations_non_reconnues()
+                .iter()
+                .any(|(formation, _)| formation == "Greffe")
+        );
+    }
 }